@@ -0,0 +1,322 @@
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    grid::GridMaster,
+    heightmap::FieldCell,
+    simulation::{SimRng, Simulation},
+    AutoCube, ChunkState, ChunkStates,
+};
+// cellular automaton mode for a chunk
+// -- the chunk bounds are cut into cells of `cell` size, that's the grid
+// -- every time the chunk steps the grid takes one life-like step
+// -- live cells get a cube, dead cells lose theirs
+// -- cubes the chunk grew before it switched are cleared with the new grid
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct LifeRule {
+    pub birth: Vec<u8>,
+    pub survive: Vec<u8>,
+}
+
+impl LifeRule {
+    // B/S notation, "B3/S23"
+    // counts above 9 (3d rules) are comma separated, ranges allowed: "B5-7/S4,5,6"
+    pub fn parse(notation: &str) -> Option<LifeRule> {
+        let mut birth = None;
+        let mut survive = None;
+        for part in notation.trim().split('/') {
+            let mut chars = part.trim().chars();
+            let kind = chars.next()?;
+            let counts = parse_counts(chars.as_str())?;
+            match kind {
+                'B' | 'b' => birth = Some(counts),
+                'S' | 's' => survive = Some(counts),
+                _ => return None,
+            }
+        }
+        Some(LifeRule {
+            birth: birth?,
+            survive: survive?,
+        })
+    }
+
+    pub fn notation(&self) -> String {
+        let join = |counts: &[u8]| {
+            let separator = match counts.iter().any(|c| *c > 9) {
+                true => ",",
+                false => "",
+            };
+            counts
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>()
+                .join(separator)
+        };
+        format!("B{}/S{}", join(&self.birth), join(&self.survive))
+    }
+
+    pub fn next(&self, alive: bool, neighbours: u8) -> bool {
+        match alive {
+            true => self.survive.contains(&neighbours),
+            false => self.birth.contains(&neighbours),
+        }
+    }
+}
+
+fn parse_counts(counts: &str) -> Option<Vec<u8>> {
+    if counts.is_empty() {
+        return Some(Vec::new());
+    }
+    if !counts.contains(',') && !counts.contains('-') {
+        return counts
+            .chars()
+            .map(|c| c.to_digit(10).map(|d| d as u8))
+            .collect();
+    }
+    let mut vec = Vec::new();
+    for count in counts.split(',') {
+        match count.split_once('-') {
+            Some((from, to)) => {
+                let (from, to) = (
+                    from.trim().parse::<u8>().ok()?,
+                    to.trim().parse::<u8>().ok()?,
+                );
+                vec.extend(from..=to);
+            }
+            None => vec.push(count.trim().parse().ok()?),
+        }
+    }
+    Some(vec)
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Dimensions {
+    Two,
+    Three,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Automaton {
+    pub rule: LifeRule,
+    // what's typed in the ui, becomes the rule once it parses
+    pub notation: String,
+    pub dimensions: Dimensions,
+    // size of a cell in bounds units
+    pub cell: f32,
+    // chance for a cell to start alive
+    pub density: f32,
+}
+
+impl Default for Automaton {
+    fn default() -> Self {
+        let rule = LifeRule {
+            birth: vec![3],
+            survive: vec![2, 3],
+        };
+        Automaton {
+            notation: rule.notation(),
+            rule,
+            dimensions: Dimensions::Two,
+            cell: 0.25,
+            density: 0.3,
+        }
+    }
+}
+
+impl Automaton {
+    // cells along x, y, z for the chunk bounds
+    pub fn grid_size(&self, chunk: &ChunkState) -> (usize, usize, usize) {
+        let size = (chunk.bounds.max - chunk.bounds.min).abs() / self.cell.max(0.01);
+        let depth = match self.dimensions {
+            Dimensions::Two => 1,
+            Dimensions::Three => size.z.ceil().max(1.0) as usize,
+        };
+        (
+            size.x.ceil().max(1.0) as usize,
+            size.y.ceil().max(1.0) as usize,
+            depth,
+        )
+    }
+
+    pub fn cell_translation(&self, chunk: &ChunkState, x: usize, y: usize, z: usize) -> Vec3 {
+        let cell = Vec3::new(x as f32, y as f32, z as f32) + 0.5;
+        (chunk.bounds.min + cell * self.cell) * chunk.scale
+    }
+}
+
+#[derive(Component)]
+pub struct Cell {
+    pub x: usize,
+    pub y: usize,
+    pub z: usize,
+}
+
+// one grid per chunk, None until the chunk switches to automaton mode
+#[derive(Resource, Default)]
+pub struct GridMasters(pub Vec<Option<GridMaster>>);
+
+pub fn update_automaton(
+    simulation: Res<Simulation>,
+    mut sim_rng: ResMut<SimRng>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    chunk_states: Res<ChunkStates>,
+    mut masters: ResMut<GridMasters>,
+    cells: Query<(Entity, &AutoCube, &Cell)>,
+    blocks: Query<(Entity, &AutoCube), (Without<Cell>, Without<FieldCell>)>,
+) {
+    let masters = &mut masters.0;
+    masters.resize_with(chunk_states.0.len(), || None);
+
+    for (index, chunk) in chunk_states.0.iter().enumerate() {
+        let Some(automaton) = &chunk.automaton else {
+            // chunk left automaton mode, clear its cells
+            if masters[index].take().is_some() {
+                for (entity, block, _) in cells.iter() {
+                    if block.index == index {
+                        commands.entity(entity).despawn_recursive();
+                    }
+                }
+            }
+            continue;
+        };
+
+        // cell size or dimensions changed, start over on a new grid
+        let grid_size = automaton.grid_size(chunk);
+        if masters[index]
+            .as_ref()
+            .is_some_and(|master| master.size() != grid_size)
+        {
+            masters[index] = None;
+            for (entity, block, _) in cells.iter() {
+                if block.index == index {
+                    commands.entity(entity).despawn_recursive();
+                }
+            }
+        }
+
+        let master = match &mut masters[index] {
+            Some(master) => master,
+            None => {
+                // the cubes the chunk grew before take no part in the automaton
+                for (entity, block) in blocks.iter() {
+                    if block.index == index {
+                        commands.entity(entity).despawn_recursive();
+                    }
+                }
+                let (width, height, depth) = grid_size;
+                let mut master = GridMaster::new(width, height, depth);
+                let rng = sim_rng.rng();
+                for z in 0..depth {
+                    for y in 0..height {
+                        for x in 0..width {
+                            if rng.gen_bool(automaton.density.clamp(0.0, 1.0) as f64) {
+                                master.set_occupied(x, y, z, true);
+                            }
+                        }
+                    }
+                }
+                for (x, y, z) in master.occupied() {
+                    spawn_cell(
+                        &mut commands,
+                        &mut meshes,
+                        &mut materials,
+                        chunk,
+                        automaton,
+                        index,
+                        (x, y, z),
+                    );
+                }
+                masters[index] = Some(master);
+                continue;
+            }
+        };
+
+        if !simulation.stepping(index) {
+            continue;
+        }
+
+        let (born, died) = master.step(|alive, neighbours| automaton.rule.next(alive, neighbours));
+
+        for (entity, block, cell) in cells.iter() {
+            if block.index == index && died.contains(&(cell.x, cell.y, cell.z)) {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+        for cell in born {
+            spawn_cell(
+                &mut commands,
+                &mut meshes,
+                &mut materials,
+                chunk,
+                automaton,
+                index,
+                cell,
+            );
+        }
+    }
+}
+
+fn spawn_cell(
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    chunk: &ChunkState,
+    automaton: &Automaton,
+    index: usize,
+    (x, y, z): (usize, usize, usize),
+) {
+    let size = automaton.cell * chunk.scale * 0.9;
+    commands
+        .spawn(PbrBundle {
+            mesh: meshes.add(Mesh::from(shape::Cube { size })),
+            material: materials.add(StandardMaterial {
+                base_color: chunk.base_color,
+                perceptual_roughness: chunk.perceptual_roughness,
+                reflectance: 0.1,
+                ..default()
+            }),
+            transform: Transform::from_translation(automaton.cell_translation(chunk, x, y, z)),
+            ..default()
+        })
+        .insert(AutoCube {
+            life_time: chunk.life_time,
+            index,
+            size,
+            color: None,
+        })
+        .insert(Cell { x, y, z });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_life_rules() {
+        let rule = LifeRule::parse("B3/S23").unwrap();
+        assert_eq!(rule.birth, vec![3]);
+        assert_eq!(rule.survive, vec![2, 3]);
+        assert_eq!(rule.notation(), "B3/S23");
+
+        let rule = LifeRule::parse(" s4,5,6 / b5-7 ").unwrap();
+        assert_eq!(rule.birth, vec![5, 6, 7]);
+        assert_eq!(rule.survive, vec![4, 5, 6]);
+
+        let rule = LifeRule::parse("B/S12,13").unwrap();
+        assert!(rule.birth.is_empty());
+        assert_eq!(rule.notation(), "B/S12,13");
+    }
+
+    #[test]
+    fn rejects_broken_rules() {
+        assert_eq!(LifeRule::parse(""), None);
+        assert_eq!(LifeRule::parse("B3"), None);
+        assert_eq!(LifeRule::parse("B3/X23"), None);
+        assert_eq!(LifeRule::parse("B3/S2a"), None);
+        assert_eq!(LifeRule::parse("B7-x/S2"), None);
+    }
+}
@@ -0,0 +1,283 @@
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+use bevy::prelude::Color;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{ortho::hex, scene::Scene, Bounds};
+// parameter sweeps over a scene, for the batch command
+// -- a sweep file lists values for some fields, every combination of them
+//    is a variation of the scene and gets rendered on its own
+// -- chunk fields apply to every chunk or only to the listed ones
+// -- numbers are a list or an evenly spaced range
+// -- the values that made a variation go into its file name and sidecar
+//
+//   { "parameters": [
+//       { "seed": [1, 2, 3] },
+//       { "life_time": { "from": 20, "to": 80, "steps": 4 }, "chunks": [0] },
+//       { "base_color": [{ "Rgba": { "red": 1.0, "green": 0.2, "blue": 0.2, "alpha": 1.0 } }] }
+//   ] }
+
+pub const BATCHES_DIR: &str = "out/batches";
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum Values<T> {
+    // `steps` values from `from` to `to`, both included
+    Range { from: f64, to: f64, steps: usize },
+    List(Vec<T>),
+}
+
+impl<T: Clone> Values<T> {
+    fn expand(&self, from_f64: impl Fn(f64) -> T) -> Vec<T> {
+        match self {
+            Values::List(values) => values.clone(),
+            Values::Range { from, to, steps } => (0..*steps)
+                .map(|step| {
+                    let t = match steps {
+                        1 => 0.,
+                        _ => step as f64 / (steps - 1) as f64,
+                    };
+                    from_f64(from + (to - from) * t)
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Sweep {
+    Seed(Values<u64>),
+    LifeTime(Values<i32>),
+    Scale(Values<f32>),
+    BaseColor(Vec<Color>),
+    EmissiveColor(Vec<Color>),
+    Bounds(Vec<Bounds>),
+}
+
+#[derive(Deserialize)]
+pub struct Parameter {
+    #[serde(flatten)]
+    pub sweep: Sweep,
+    // chunks the values apply to, all of them when missing
+    #[serde(default)]
+    pub chunks: Option<Vec<usize>>,
+}
+
+// one value of a sweep
+#[derive(Clone)]
+enum Setting {
+    Seed(u64),
+    LifeTime(i32),
+    Scale(f32),
+    BaseColor(Color),
+    EmissiveColor(Color),
+    Bounds(Bounds),
+}
+
+impl Parameter {
+    fn name(&self) -> &'static str {
+        match self.sweep {
+            Sweep::Seed(_) => "seed",
+            Sweep::LifeTime(_) => "life_time",
+            Sweep::Scale(_) => "scale",
+            Sweep::BaseColor(_) => "base_color",
+            Sweep::EmissiveColor(_) => "emissive_color",
+            Sweep::Bounds(_) => "bounds",
+        }
+    }
+
+    // name in the sidecar, with the chunks when it only applies to some
+    fn key(&self) -> String {
+        match &self.chunks {
+            Some(chunks) => format!("{}{:?}", self.name(), chunks),
+            None => self.name().into(),
+        }
+    }
+
+    fn settings(&self) -> Vec<Setting> {
+        match &self.sweep {
+            Sweep::Seed(values) => values
+                .expand(|x| x.round().max(0.) as u64)
+                .into_iter()
+                .map(Setting::Seed)
+                .collect(),
+            Sweep::LifeTime(values) => values
+                .expand(|x| x.round() as i32)
+                .into_iter()
+                .map(Setting::LifeTime)
+                .collect(),
+            Sweep::Scale(values) => values
+                .expand(|x| x as f32)
+                .into_iter()
+                .map(Setting::Scale)
+                .collect(),
+            Sweep::BaseColor(colors) => colors.iter().copied().map(Setting::BaseColor).collect(),
+            Sweep::EmissiveColor(colors) => {
+                colors.iter().copied().map(Setting::EmissiveColor).collect()
+            }
+            Sweep::Bounds(bounds) => bounds.iter().cloned().map(Setting::Bounds).collect(),
+        }
+    }
+}
+
+impl Setting {
+    fn apply(&self, scene: &mut Scene, chunks: &Option<Vec<usize>>) {
+        if let Setting::Seed(seed) = self {
+            scene.seed = *seed;
+            return;
+        }
+        for (index, chunk) in scene.chunks.iter_mut().enumerate() {
+            if chunks
+                .as_ref()
+                .is_some_and(|chunks| !chunks.contains(&index))
+            {
+                continue;
+            }
+            match self {
+                Setting::Seed(_) => {}
+                Setting::LifeTime(life_time) => chunk.life_time = *life_time,
+                Setting::Scale(scale) => chunk.scale = *scale,
+                Setting::BaseColor(color) => chunk.base_color = *color,
+                Setting::EmissiveColor(color) => chunk.emissive_color = *color,
+                Setting::Bounds(bounds) => chunk.bounds = bounds.clone(),
+            }
+        }
+    }
+
+    // for file names, bounds go by their position in the list
+    fn label(&self, index: usize) -> String {
+        match self {
+            Setting::Seed(seed) => seed.to_string(),
+            Setting::LifeTime(life_time) => life_time.to_string(),
+            // dots would be taken for the extension
+            Setting::Scale(scale) => scale.to_string().replace('.', "p"),
+            Setting::BaseColor(color) | Setting::EmissiveColor(color) => {
+                hex(*color).trim_start_matches('#').into()
+            }
+            Setting::Bounds(_) => index.to_string(),
+        }
+    }
+
+    fn value(&self) -> Value {
+        match self {
+            Setting::Seed(seed) => json!(seed),
+            Setting::LifeTime(life_time) => json!(life_time),
+            Setting::Scale(scale) => json!(scale),
+            Setting::BaseColor(color) | Setting::EmissiveColor(color) => json!(hex(*color)),
+            Setting::Bounds(bounds) => serde_json::to_value(bounds).unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Variation {
+    // file name of the still, a timestamp when None
+    pub name: Option<String>,
+    pub scene: Scene,
+    // what the sweep set, for the sidecar
+    pub parameters: BTreeMap<String, Value>,
+}
+
+impl Variation {
+    pub fn of(scene: Scene) -> Self {
+        Variation {
+            name: None,
+            scene,
+            parameters: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Batch {
+    pub parameters: Vec<Parameter>,
+}
+
+impl Batch {
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Batch> {
+        let json = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    // every combination, named <index>_<field>-<value>_..
+    pub fn variations(&self, scene: &Scene) -> Vec<Variation> {
+        let mut variations = vec![(String::new(), Variation::of(scene.clone()))];
+        for parameter in &self.parameters {
+            let settings = parameter.settings();
+            variations = variations
+                .iter()
+                .flat_map(|(label, variation)| {
+                    settings.iter().enumerate().map(move |(index, setting)| {
+                        let mut variation = variation.clone();
+                        setting.apply(&mut variation.scene, &parameter.chunks);
+                        variation
+                            .parameters
+                            .insert(parameter.key(), setting.value());
+                        let label =
+                            format!("{}_{}-{}", label, parameter.name(), setting.label(index));
+                        (label, variation)
+                    })
+                })
+                .collect();
+        }
+
+        variations
+            .into_iter()
+            .enumerate()
+            .map(|(index, (label, mut variation))| {
+                variation.name = Some(format!("{:04}{}", index, label));
+                variation
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ChunkState;
+
+    #[test]
+    fn every_combination_is_a_variation() {
+        let batch: Batch = serde_json::from_str(
+            r#"{ "parameters": [
+                { "seed": [1, 2] },
+                { "life_time": { "from": 20, "to": 80, "steps": 3 }, "chunks": [1] }
+            ] }"#,
+        )
+        .unwrap();
+        let mut scene = Scene::base(None).unwrap();
+        scene.chunks = vec![ChunkState::default(), ChunkState::default()];
+        let untouched = scene.chunks[0].life_time;
+
+        let variations = batch.variations(&scene);
+        assert_eq!(variations.len(), 6);
+        assert_eq!(
+            variations[0].name.as_deref(),
+            Some("0000_seed-1_life_time-20")
+        );
+        assert_eq!(
+            variations[4].name.as_deref(),
+            Some("0004_seed-2_life_time-50")
+        );
+
+        let variation = &variations[4];
+        assert_eq!(variation.scene.seed, 2);
+        assert_eq!(variation.scene.chunks[0].life_time, untouched);
+        assert_eq!(variation.scene.chunks[1].life_time, 50);
+        assert_eq!(variation.parameters["seed"], json!(2));
+        assert_eq!(variation.parameters["life_time[1]"], json!(50));
+    }
+
+    #[test]
+    fn one_step_range_is_its_start() {
+        let values: Values<i32> = Values::Range {
+            from: 4.,
+            to: 9.,
+            steps: 1,
+        };
+        assert_eq!(values.expand(|x| x as i32), vec![4]);
+    }
+}
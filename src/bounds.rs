@@ -0,0 +1,218 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_egui::EguiContexts;
+use bevy_image_export::ImageExportSettings;
+
+use crate::{
+    export::Capture,
+    passes::{id_color, PassExport},
+    record::Recording,
+    setup::PlisCamera,
+    ChunkStates, UIState,
+};
+// chunk bounds in the viewport
+// -- every chunk's bounds are drawn as a box in the chunk's id color
+// -- the selected chunk gets a handle in the middle of each face, dragging a
+//    handle moves that face along its axis, straight into ChunkState.bounds
+//    so the egui values follow
+// -- handles are picked on screen and sized in pixels, so they work at any zoom
+// -- nothing is drawn while exporting, gizmos would end up in the frames
+
+// pixels around a handle that still pick it
+const HANDLE_PICK: f32 = 12.;
+const HANDLE_SIZE: f32 = 5.;
+
+#[derive(Resource, Default)]
+pub struct BoundsGizmo {
+    pub visible: bool,
+    drag: Option<FaceHandle>,
+}
+
+// a face of the selected chunk's bounds
+#[derive(Clone, Copy, PartialEq)]
+struct FaceHandle {
+    axis: usize,
+    max: bool,
+}
+
+// world position of a handle
+fn handle_position(min: Vec3, max: Vec3, handle: FaceHandle) -> Vec3 {
+    let mut position = (min + max) / 2.;
+    position[handle.axis] = match handle.max {
+        true => max[handle.axis],
+        false => min[handle.axis],
+    };
+    position
+}
+
+fn handles() -> impl Iterator<Item = FaceHandle> {
+    (0..3).flat_map(|axis| [false, true].map(|max| FaceHandle { axis, max }))
+}
+
+// how much the handle's face looks at the camera
+fn facing(transform: &GlobalTransform, handle: FaceHandle) -> f32 {
+    let side = match handle.max {
+        true => 1.,
+        false => -1.,
+    };
+    -transform.forward()[handle.axis] * side
+}
+
+// the handle under the cursor, if any, of two handles on the same spot
+// (flat bounds) the one whose face looks at the camera
+fn pick(
+    camera: &Camera,
+    transform: &GlobalTransform,
+    cursor: Vec2,
+    min: Vec3,
+    max: Vec3,
+) -> Option<FaceHandle> {
+    handles()
+        .filter_map(|handle| {
+            let screen = camera.world_to_viewport(transform, handle_position(min, max, handle))?;
+            Some((handle, screen.distance(cursor)))
+        })
+        .filter(|(_, distance)| *distance <= HANDLE_PICK)
+        .min_by(|a, b| {
+            a.1.total_cmp(&b.1)
+                .then_with(|| facing(transform, b.0).total_cmp(&facing(transform, a.0)))
+        })
+        .map(|(handle, _)| handle)
+}
+
+pub fn draw_bounds(
+    mut gizmos: Gizmos,
+    gizmo: Res<BoundsGizmo>,
+    state: Res<UIState>,
+    chunk_states: Res<ChunkStates>,
+    capture: Res<Capture>,
+    recording: Res<Recording>,
+    render: Query<&ImageExportSettings, Without<PassExport>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    camera: Query<(&Camera, &GlobalTransform, &Projection), With<PlisCamera>>,
+) {
+    let exporting = render.iter().any(|render| render.render);
+    if !gizmo.visible || exporting || capture.busy() || recording.busy() {
+        return;
+    }
+
+    for (index, chunk) in chunk_states.0.iter().enumerate() {
+        let min = chunk.bounds.min.min(chunk.bounds.max) * chunk.scale;
+        let max = chunk.bounds.min.max(chunk.bounds.max) * chunk.scale;
+        let transform = Transform::from_translation((min + max) / 2.).with_scale(max - min);
+        gizmos.cuboid(transform, id_color(index));
+    }
+
+    let Some(chunk) = chunk_states.0.get(state.selected) else {
+        return;
+    };
+    let Ok((camera, transform, projection)) = camera.get_single() else {
+        return;
+    };
+    let Some(viewport) = camera.logical_viewport_size() else {
+        return;
+    };
+    // world units per pixel
+    let pixel = match projection {
+        Projection::Orthographic(orthographic) => orthographic.area.height() / viewport.y,
+        Projection::Perspective(_) => 1.,
+    };
+    let min = chunk.bounds.min.min(chunk.bounds.max) * chunk.scale;
+    let max = chunk.bounds.min.max(chunk.bounds.max) * chunk.scale;
+    let hovered = windows
+        .get_single()
+        .ok()
+        .and_then(|window| window.cursor_position())
+        .and_then(|cursor| pick(camera, transform, cursor, min, max));
+
+    for handle in handles() {
+        let active =
+            gizmo.drag == Some(handle) || (gizmo.drag.is_none() && hovered == Some(handle));
+        let (size, color) = match active {
+            true => (HANDLE_SIZE * 1.6, Color::WHITE),
+            false => (HANDLE_SIZE, id_color(state.selected)),
+        };
+        gizmos.sphere(
+            handle_position(min, max, handle),
+            Quat::IDENTITY,
+            size * pixel,
+            color,
+        );
+    }
+}
+
+pub fn drag_bounds(
+    mut gizmo: ResMut<BoundsGizmo>,
+    mut chunk_states: ResMut<ChunkStates>,
+    state: Res<UIState>,
+    buttons: Res<Input<MouseButton>>,
+    mut contexts: EguiContexts,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    camera: Query<(&Camera, &GlobalTransform), With<PlisCamera>>,
+) {
+    if !gizmo.visible || buttons.just_released(MouseButton::Left) {
+        gizmo.drag = None;
+    }
+    if !gizmo.visible {
+        return;
+    }
+    let Some(chunk) = chunk_states.0.get(state.selected) else {
+        return;
+    };
+    let Ok((camera, transform)) = camera.get_single() else {
+        return;
+    };
+    let Some(cursor) = windows
+        .get_single()
+        .ok()
+        .and_then(|window| window.cursor_position())
+    else {
+        return;
+    };
+    let scale = chunk.scale;
+    if scale == 0. {
+        return;
+    }
+    let min = chunk.bounds.min.min(chunk.bounds.max) * scale;
+    let max = chunk.bounds.min.max(chunk.bounds.max) * scale;
+
+    if buttons.just_pressed(MouseButton::Left) && !contexts.ctx_mut().wants_pointer_input() {
+        gizmo.drag = pick(camera, transform, cursor, min, max);
+    }
+    let Some(handle) = gizmo.drag else {
+        return;
+    };
+    let Some(ray) = camera.viewport_to_world(transform, cursor) else {
+        return;
+    };
+
+    // the point of the handle's axis closest to the cursor ray
+    let start = handle_position(min, max, handle);
+    let mut axis = Vec3::ZERO;
+    axis[handle.axis] = 1.;
+    let along = axis.dot(ray.direction);
+    let denom = 1. - along * along;
+    // looking straight down the axis, nothing to drag along
+    if denom < 1e-4 {
+        return;
+    }
+    let offset = start - ray.origin;
+    let t = (along * ray.direction.dot(offset) - axis.dot(offset)) / denom;
+    let value = (start[handle.axis] + t) / scale;
+
+    // faces can't pass each other
+    let bounds = &mut chunk_states.0[state.selected].bounds;
+    let (low, high) = (
+        bounds.min[handle.axis].min(bounds.max[handle.axis]),
+        bounds.min[handle.axis].max(bounds.max[handle.axis]),
+    );
+    let value = match handle.max {
+        true => value.max(low),
+        false => value.min(high),
+    };
+    // the face is whichever of min and max is on that side
+    let min_is_low = bounds.min[handle.axis] <= bounds.max[handle.axis];
+    match handle.max == min_is_low {
+        true => bounds.max[handle.axis] = value,
+        false => bounds.min[handle.axis] = value,
+    }
+}
@@ -0,0 +1,408 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        camera::RenderTarget,
+        render_resource::{
+            Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+        },
+    },
+    window::PrimaryWindow,
+};
+use bevy_image_export::{ImageExportSettings, ImageExportSource};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    passes::{Pass, PassCamera, PassExport, Passes},
+    record::raw_frames,
+    scene::{CameraState, Scene},
+    setup::PlisCamera,
+    simulation::{SimRng, Simulation},
+    ChunkStates,
+};
+// size of the exported frames
+// -- either pixels straight away or a print size at some dpi
+// -- the window keeps the same aspect ratio so the preview frames
+//    exactly what gets exported
+// -- changing the size recreates the export texture
+
+// largest texture side wgpu gives us by default
+pub const MAX_EXPORT_SIZE: u32 = 8192;
+// longest window side, the other one follows the export aspect ratio
+pub const PREVIEW_SIZE: f32 = 768.;
+
+#[derive(Component)]
+pub struct ExportCamera;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PrintSize {
+    pub name: &'static str,
+    pub width_mm: f32,
+    pub height_mm: f32,
+}
+
+pub const PRINT_SIZES: [PrintSize; 6] = [
+    PrintSize {
+        name: "A5",
+        width_mm: 148.,
+        height_mm: 210.,
+    },
+    PrintSize {
+        name: "A4",
+        width_mm: 210.,
+        height_mm: 297.,
+    },
+    PrintSize {
+        name: "A3",
+        width_mm: 297.,
+        height_mm: 420.,
+    },
+    PrintSize {
+        name: "A2",
+        width_mm: 420.,
+        height_mm: 594.,
+    },
+    PrintSize {
+        name: "Square 30cm",
+        width_mm: 300.,
+        height_mm: 300.,
+    },
+    PrintSize {
+        name: "Poster 50x70",
+        width_mm: 500.,
+        height_mm: 700.,
+    },
+];
+
+#[derive(Resource, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ExportSettings {
+    pub width: u32,
+    pub height: u32,
+    // only used to turn print sizes into pixels, and noted in the sidecar
+    pub dpi: f32,
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        ExportSettings {
+            width: 1584 * 2,
+            height: 1584 * 2,
+            dpi: 300.,
+        }
+    }
+}
+
+impl ExportSettings {
+    // past MAX_EXPORT_SIZE the dpi is lowered so both sides shrink alike
+    pub fn from_print(print: &PrintSize, dpi: f32, landscape: bool) -> Self {
+        let longest = print.width_mm.max(print.height_mm) / 25.4;
+        let max_dpi = (MAX_EXPORT_SIZE as f32 / longest).floor();
+        let dpi = match dpi > max_dpi {
+            true => {
+                warn!(
+                    "{} at {} dpi is over {} pixels, exporting at {} dpi",
+                    print.name, dpi, MAX_EXPORT_SIZE, max_dpi
+                );
+                max_dpi
+            }
+            false => dpi,
+        };
+        let to_pixels = |mm: f32| ((mm / 25.4 * dpi).round() as u32).clamp(1, MAX_EXPORT_SIZE);
+        let (width, height) = (to_pixels(print.width_mm), to_pixels(print.height_mm));
+        let (width, height) = match landscape {
+            true => (height, width),
+            false => (width, height),
+        };
+        ExportSettings { width, height, dpi }
+    }
+
+    pub fn aspect(&self) -> f32 {
+        self.width as f32 / self.height.max(1) as f32
+    }
+
+    pub fn extent(&self) -> Extent3d {
+        Extent3d {
+            width: self.width.clamp(1, MAX_EXPORT_SIZE),
+            height: self.height.clamp(1, MAX_EXPORT_SIZE),
+            ..default()
+        }
+    }
+
+    // print size in mm at the current dpi
+    pub fn print_mm(&self) -> Vec2 {
+        Vec2::new(self.width as f32, self.height as f32) / self.dpi.max(1.) * 25.4
+    }
+
+    pub fn preview_size(&self) -> Vec2 {
+        match self.aspect() >= 1. {
+            true => Vec2::new(PREVIEW_SIZE, PREVIEW_SIZE / self.aspect()),
+            false => Vec2::new(PREVIEW_SIZE * self.aspect(), PREVIEW_SIZE),
+        }
+    }
+}
+
+pub fn export_texture(
+    images: &mut Assets<Image>,
+    settings: &ExportSettings,
+    format: TextureFormat,
+) -> Handle<Image> {
+    let size = settings.extent();
+    let mut export_texture = Image {
+        texture_descriptor: TextureDescriptor {
+            label: None,
+            size,
+            dimension: TextureDimension::D2,
+            format,
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::COPY_DST
+                | TextureUsages::COPY_SRC
+                | TextureUsages::RENDER_ATTACHMENT
+                | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        },
+        ..default()
+    };
+    export_texture.resize(size);
+
+    images.add(export_texture)
+}
+
+pub fn resize_export(
+    settings: Res<ExportSettings>,
+    mut images: ResMut<Assets<Image>>,
+    mut export_sources: ResMut<Assets<ImageExportSource>>,
+    mut cameras: Query<&mut Camera, With<ExportCamera>>,
+    mut exports: Query<&mut Handle<ImageExportSource>, Without<PassExport>>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    if !settings.is_changed() || settings.is_added() {
+        return;
+    }
+
+    let handle = export_texture(&mut images, &settings, TextureFormat::Rgba8UnormSrgb);
+    for mut camera in cameras.iter_mut() {
+        camera.target = RenderTarget::Image(handle.clone());
+    }
+    for mut export in exports.iter_mut() {
+        *export = export_sources.add(handle.clone().into());
+    }
+
+    let preview = settings.preview_size();
+    for mut window in windows.iter_mut() {
+        window.resolution.set(preview.x, preview.y);
+    }
+}
+
+// the export and pass cameras frame whatever the preview camera frames
+pub fn sync_export_projection(
+    preview: Query<&Projection, (With<PlisCamera>, Without<ExportCamera>, Without<PassCamera>)>,
+    mut export: Query<&mut Projection, Or<(With<ExportCamera>, With<PassCamera>)>>,
+) {
+    let Ok(preview) = preview.get_single() else {
+        return;
+    };
+    for mut projection in export.iter_mut() {
+        *projection = preview.clone();
+    }
+}
+
+// single frame stills
+// -- the simulation is paused and the export camera gets `warmup` frames
+//    for taa to converge, then exactly one frame is rendered
+// -- the exporter writes into a scratch folder, the frame is then moved to
+//    out/stills/<timestamp>_seed<seed>.<ext> with the scene next to it as .json
+//    and the enabled passes as <timestamp>_seed<seed>.<pass>.<ext>
+
+pub const STILLS_DIR: &str = "out/stills";
+// frames to wait for the exporter to write the still before giving up
+const COLLECT_TIMEOUT: u32 = 600;
+
+#[derive(Resource)]
+pub struct Capture {
+    pub warmup: u32,
+    // where stills go, STILLS_DIR unless rendering from the command line
+    pub dir: PathBuf,
+    // file name of the next still instead of a timestamp
+    pub name: Option<String>,
+    // noted in the next still's sidecar
+    pub parameters: BTreeMap<String, Value>,
+    pub requested: bool,
+    // stills written since the start, headless rendering checks it
+    pub written: usize,
+    state: CaptureState,
+}
+
+impl Default for Capture {
+    fn default() -> Self {
+        Capture {
+            warmup: 30,
+            dir: STILLS_DIR.into(),
+            name: None,
+            parameters: BTreeMap::new(),
+            requested: false,
+            written: 0,
+            state: CaptureState::Idle,
+        }
+    }
+}
+
+impl Capture {
+    pub fn busy(&self) -> bool {
+        !matches!(self.state, CaptureState::Idle)
+    }
+}
+
+struct Still {
+    name: String,
+    dir: PathBuf,
+    scratch: PathBuf,
+    // where the exporter wrote before the capture
+    output_dir: String,
+    was_paused: bool,
+    scene: Scene,
+    passes: Vec<Pass>,
+}
+
+enum CaptureState {
+    Idle,
+    Warmup(Still, u32),
+    Render(Still),
+    Collect(Still, u32),
+}
+
+// <unix timestamp>_seed<seed>
+pub fn capture_name(seed: u64) -> String {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    format!("{}_seed{}", timestamp, seed)
+}
+
+pub fn capture_still(
+    mut capture: ResMut<Capture>,
+    mut simulation: ResMut<Simulation>,
+    sim_rng: Res<SimRng>,
+    chunk_states: Res<ChunkStates>,
+    export_settings: Res<ExportSettings>,
+    passes: Res<Passes>,
+    camera: Query<(&Transform, &Projection), With<PlisCamera>>,
+    mut render: Query<&mut ImageExportSettings, Without<PassExport>>,
+) {
+    let Ok(mut render) = render.get_single_mut() else {
+        return;
+    };
+
+    let state = std::mem::replace(&mut capture.state, CaptureState::Idle);
+    capture.state = match state {
+        CaptureState::Idle => {
+            if !capture.requested {
+                return;
+            }
+            capture.requested = false;
+
+            let Ok((transform, projection)) = camera.get_single() else {
+                return;
+            };
+            let name = capture
+                .name
+                .take()
+                .unwrap_or_else(|| capture_name(sim_rng.seed));
+            let scratch = capture.dir.join(format!(".{}", name));
+            if let Err(err) = fs::create_dir_all(&scratch) {
+                warn!("could not create {:?}: {}", scratch, err);
+                return;
+            }
+
+            let still = Still {
+                name,
+                dir: capture.dir.clone(),
+                output_dir: render.output_dir.clone(),
+                scratch,
+                was_paused: simulation.paused,
+                scene: Scene {
+                    seed: sim_rng.seed,
+                    tick: simulation.tick,
+                    export: export_settings.clone(),
+                    camera: CameraState::new(transform, projection),
+                    chunks: chunk_states.0.clone(),
+                    parameters: std::mem::take(&mut capture.parameters),
+                    sequence: None,
+                },
+                passes: passes.enabled.clone(),
+            };
+            simulation.paused = true;
+            render.render = false;
+            render.output_dir = still.scratch.to_string_lossy().into_owned();
+            CaptureState::Warmup(still, capture.warmup)
+        }
+        CaptureState::Warmup(still, 0) => {
+            render.render = true;
+            CaptureState::Render(still)
+        }
+        CaptureState::Warmup(still, frames) => CaptureState::Warmup(still, frames - 1),
+        CaptureState::Render(still) => {
+            render.render = false;
+            CaptureState::Collect(still, 0)
+        }
+        CaptureState::Collect(still, frames) => {
+            let frame = raw_frames(&still.scratch, &render.extension).pop();
+            let pass_frames: Vec<(Pass, Option<PathBuf>)> = still
+                .passes
+                .iter()
+                .map(|pass| {
+                    let dir = pass.dir(&still.scratch);
+                    (*pass, raw_frames(&dir, pass.extension()).pop())
+                })
+                .collect();
+            let complete = pass_frames.iter().all(|(_, frame)| frame.is_some());
+
+            match frame {
+                Some(frame) if complete || frames >= COLLECT_TIMEOUT => {
+                    let file = still.dir.join(&still.name);
+                    let image = file.with_extension(&render.extension);
+                    match fs::rename(&frame, &image) {
+                        Ok(_) => {
+                            println!("captured {:?}", image);
+                            capture.written += 1;
+                        }
+                        Err(err) => warn!("could not move {:?}: {}", frame, err),
+                    }
+                    for (pass, pass_frame) in pass_frames {
+                        let Some(pass_frame) = pass_frame else {
+                            warn!("{} pass of {} was never written", pass.name(), still.name);
+                            continue;
+                        };
+                        if let Err(err) = fs::rename(&pass_frame, pass.file(&image)) {
+                            warn!("could not move {:?}: {}", pass_frame, err);
+                        }
+                    }
+                    if let Err(err) = still.scene.write(file.with_extension("json")) {
+                        warn!("could not write the sidecar for {}: {}", still.name, err);
+                    }
+                    finish_still(still, &mut simulation, &mut render);
+                    CaptureState::Idle
+                }
+                None if frames >= COLLECT_TIMEOUT => {
+                    warn!("still {} was never written", still.name);
+                    finish_still(still, &mut simulation, &mut render);
+                    CaptureState::Idle
+                }
+                _ => CaptureState::Collect(still, frames + 1),
+            }
+        }
+    };
+}
+
+fn finish_still(still: Still, simulation: &mut Simulation, render: &mut ImageExportSettings) {
+    let _ = fs::remove_dir_all(&still.scratch);
+    render.output_dir = still.output_dir;
+    simulation.paused = still.was_paused;
+}
@@ -0,0 +1,481 @@
+use std::{fmt::Write, fs, io, path::Path};
+
+use bevy::{
+    prelude::*,
+    render::mesh::{PrimitiveTopology, VertexAttributeValues},
+};
+use serde_json::{json, Value};
+
+use crate::{
+    export::capture_name,
+    ortho::{collect_cubes, hex, CubeGeometry},
+    outline::Outline,
+    simulation::SimRng,
+    AutoCube, ChunkStates,
+};
+// the live scene as a mesh file for blender or a slicer
+// -- every AutoCube becomes a closed box in world space, one object per chunk
+// -- outline meshes go into their own object
+// -- cubes sharing a color share a material
+// -- obj comes with an .mtl, gltf with a .bin next to it,
+//    stl is binary and z up like slicers expect
+
+pub const GEOMETRY_DIR: &str = "out/geometry";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GeometryFormat {
+    Obj,
+    Gltf,
+    Stl,
+}
+
+impl GeometryFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            GeometryFormat::Obj => "OBJ",
+            GeometryFormat::Gltf => "glTF",
+            GeometryFormat::Stl => "STL",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            GeometryFormat::Obj => "obj",
+            GeometryFormat::Gltf => "gltf",
+            GeometryFormat::Stl => "stl",
+        }
+    }
+
+    pub fn all() -> [GeometryFormat; 3] {
+        [
+            GeometryFormat::Obj,
+            GeometryFormat::Gltf,
+            GeometryFormat::Stl,
+        ]
+    }
+}
+
+#[derive(Resource)]
+pub struct GeometryExport {
+    pub format: GeometryFormat,
+    pub outlines: bool,
+    // multiplies every coordinate, the scene is in SCALE units
+    pub unit_scale: f32,
+    pub requested: bool,
+}
+
+impl Default for GeometryExport {
+    fn default() -> Self {
+        GeometryExport {
+            format: GeometryFormat::Gltf,
+            outlines: true,
+            unit_scale: 1.,
+            requested: false,
+        }
+    }
+}
+
+// triangles of one object sharing a material, in world space
+pub struct Part {
+    pub object: String,
+    pub color: Color,
+    pub roughness: f32,
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub indices: Vec<u32>,
+}
+
+impl Part {
+    fn new(object: String, color: Color, roughness: f32) -> Self {
+        Part {
+            object,
+            color,
+            roughness,
+            positions: Vec::new(),
+            normals: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    fn material(&self) -> String {
+        format!(
+            "{}_r{}",
+            hex(self.color).trim_start_matches('#'),
+            (self.roughness * 100.).round() as u32
+        )
+    }
+
+    fn triangles(&self) -> impl Iterator<Item = [usize; 3]> + '_ {
+        self.indices
+            .chunks_exact(3)
+            .map(|t| [t[0] as usize, t[1] as usize, t[2] as usize])
+    }
+
+    fn add_cube(&mut self, cube: &CubeGeometry) {
+        for face in cube.faces() {
+            let base = self.positions.len() as u32;
+            self.positions.extend(face.corners);
+            self.normals.extend([face.normal; 4]);
+            // ortho faces go clockwise seen from outside, flip them
+            self.indices.extend([0, 2, 1, 0, 3, 2].map(|i| base + i));
+        }
+    }
+
+    // only triangle lists with float positions, which is all bevy's shapes make
+    fn add_mesh(&mut self, mesh: &Mesh, transform: &GlobalTransform) {
+        if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+            return;
+        }
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            return;
+        };
+        let normals = match mesh.attribute(Mesh::ATTRIBUTE_NORMAL) {
+            Some(VertexAttributeValues::Float32x3(normals)) => normals.clone(),
+            _ => vec![[0.; 3]; positions.len()],
+        };
+        let normal_matrix = Mat3::from(transform.affine().matrix3).inverse().transpose();
+
+        let base = self.positions.len() as u32;
+        self.positions.extend(
+            positions
+                .iter()
+                .map(|position| transform.transform_point(Vec3::from(*position))),
+        );
+        self.normals.extend(
+            normals
+                .iter()
+                .map(|normal| (normal_matrix * Vec3::from(*normal)).normalize_or_zero()),
+        );
+        match mesh.indices() {
+            Some(indices) => self.indices.extend(indices.iter().map(|i| base + i as u32)),
+            None => self
+                .indices
+                .extend((0..positions.len() as u32).map(|i| base + i)),
+        }
+    }
+}
+
+// the color of the first vertex, outline meshes are one color throughout
+fn vertex_color(mesh: &Mesh) -> Option<Color> {
+    match mesh.attribute(Mesh::ATTRIBUTE_COLOR)? {
+        VertexAttributeValues::Float32x4(colors) => {
+            let [r, g, b, a] = *colors.first()?;
+            Some(Color::rgba_linear(r, g, b, a))
+        }
+        _ => None,
+    }
+}
+
+// the part of `object` with the material of (color, roughness), made if missing
+fn part(parts: &mut Vec<Part>, object: String, color: Color, roughness: f32) -> &mut Part {
+    let key = Part::new(object, color, roughness);
+    match parts
+        .iter()
+        .position(|part| part.object == key.object && part.material() == key.material())
+    {
+        Some(index) => &mut parts[index],
+        None => {
+            parts.push(key);
+            parts.last_mut().unwrap()
+        }
+    }
+}
+
+pub fn write_obj(file: &Path, parts: &[Part]) -> io::Result<()> {
+    let mtl = file.with_extension("mtl");
+    let mut obj = String::new();
+    let mut materials = String::new();
+    let mut written: Vec<String> = Vec::new();
+    let _ = writeln!(
+        obj,
+        "mtllib {}",
+        mtl.file_name().unwrap_or_default().to_string_lossy()
+    );
+
+    // obj indices are 1 based and global
+    let mut offset = 1;
+    let mut object = "";
+    for part in parts {
+        if part.object != object {
+            let _ = writeln!(obj, "o {}", part.object);
+            object = &part.object;
+        }
+        let material = part.material();
+        if !written.contains(&material) {
+            let [r, g, b, a] = part.color.as_rgba_f32();
+            let _ = writeln!(
+                materials,
+                "newmtl {}\nKd {} {} {}\nd {}\nPr {}\n",
+                material, r, g, b, a, part.roughness
+            );
+            written.push(material.clone());
+        }
+        let _ = writeln!(obj, "usemtl {}", material);
+
+        for p in &part.positions {
+            let _ = writeln!(obj, "v {} {} {}", p.x, p.y, p.z);
+        }
+        for n in &part.normals {
+            let _ = writeln!(obj, "vn {} {} {}", n.x, n.y, n.z);
+        }
+        for [a, b, c] in part.triangles() {
+            let _ = writeln!(
+                obj,
+                "f {0}//{0} {1}//{1} {2}//{2}",
+                offset + a,
+                offset + b,
+                offset + c
+            );
+        }
+        offset += part.positions.len();
+    }
+
+    fs::write(&mtl, materials)?;
+    fs::write(file, obj)
+}
+
+pub fn write_stl(file: &Path, parts: &[Part]) -> io::Result<()> {
+    // a quarter turn around x, y up becomes z up
+    let z_up = |v: Vec3| Vec3::new(v.x, -v.z, v.y);
+    let count: usize = parts.iter().map(|part| part.indices.len() / 3).sum();
+
+    let mut bytes = Vec::with_capacity(84 + count * 50);
+    let mut header = [0u8; 80];
+    let name = b"design_to_blocks";
+    header[..name.len()].copy_from_slice(name);
+    bytes.extend_from_slice(&header);
+    bytes.extend((count as u32).to_le_bytes());
+
+    for part in parts {
+        for [a, b, c] in part.triangles() {
+            let [a, b, c] = [a, b, c].map(|i| z_up(part.positions[i]));
+            let normal = (b - a).cross(c - a).normalize_or_zero();
+            for v in [normal, a, b, c] {
+                for x in v.to_array() {
+                    bytes.extend(x.to_le_bytes());
+                }
+            }
+            // attribute byte count, unused
+            bytes.extend(0u16.to_le_bytes());
+        }
+    }
+    fs::write(file, bytes)
+}
+
+const GLTF_FLOAT: u32 = 5126;
+const GLTF_UNSIGNED_INT: u32 = 5125;
+const GLTF_ARRAY_BUFFER: u32 = 34962;
+const GLTF_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+// appends the bytes as a buffer view and returns its index
+fn push_view(buffer: &mut Vec<u8>, views: &mut Vec<Value>, bytes: Vec<u8>, target: u32) -> usize {
+    views.push(json!({
+        "buffer": 0,
+        "byteOffset": buffer.len(),
+        "byteLength": bytes.len(),
+        "target": target,
+    }));
+    buffer.extend(bytes);
+    views.len() - 1
+}
+
+fn vec3_bytes(vectors: &[Vec3]) -> Vec<u8> {
+    vectors
+        .iter()
+        .flat_map(|v| v.to_array())
+        .flat_map(|x| x.to_le_bytes())
+        .collect()
+}
+
+// one node and mesh per object, one primitive per part
+pub fn write_gltf(file: &Path, parts: &[Part]) -> io::Result<()> {
+    let bin = file.with_extension("bin");
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut views = Vec::new();
+    let mut accessors = Vec::new();
+    let mut materials: Vec<(String, Value)> = Vec::new();
+    let mut objects: Vec<(&str, Vec<Value>)> = Vec::new();
+
+    for part in parts.iter().filter(|part| !part.indices.is_empty()) {
+        let (min, max) = part.positions.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), p| (min.min(*p), max.max(*p)),
+        );
+        let view = push_view(
+            &mut buffer,
+            &mut views,
+            vec3_bytes(&part.positions),
+            GLTF_ARRAY_BUFFER,
+        );
+        accessors.push(json!({
+            "bufferView": view,
+            "componentType": GLTF_FLOAT,
+            "count": part.positions.len(),
+            "type": "VEC3",
+            "min": min.to_array(),
+            "max": max.to_array(),
+        }));
+        let positions = accessors.len() - 1;
+
+        let view = push_view(
+            &mut buffer,
+            &mut views,
+            vec3_bytes(&part.normals),
+            GLTF_ARRAY_BUFFER,
+        );
+        accessors.push(json!({
+            "bufferView": view,
+            "componentType": GLTF_FLOAT,
+            "count": part.normals.len(),
+            "type": "VEC3",
+        }));
+        let normals = accessors.len() - 1;
+
+        let indices = part.indices.iter().flat_map(|i| i.to_le_bytes()).collect();
+        let view = push_view(&mut buffer, &mut views, indices, GLTF_ELEMENT_ARRAY_BUFFER);
+        accessors.push(json!({
+            "bufferView": view,
+            "componentType": GLTF_UNSIGNED_INT,
+            "count": part.indices.len(),
+            "type": "SCALAR",
+        }));
+        let indices = accessors.len() - 1;
+
+        let name = part.material();
+        let material = match materials.iter().position(|(key, _)| *key == name) {
+            Some(material) => material,
+            None => {
+                let color = part.color.as_linear_rgba_f32();
+                let blend = match color[3] < 1. {
+                    true => "BLEND",
+                    false => "OPAQUE",
+                };
+                materials.push((
+                    name.clone(),
+                    json!({
+                        "name": name,
+                        "pbrMetallicRoughness": {
+                            "baseColorFactor": color,
+                            "metallicFactor": 0.0,
+                            "roughnessFactor": part.roughness,
+                        },
+                        "alphaMode": blend,
+                    }),
+                ));
+                materials.len() - 1
+            }
+        };
+
+        let primitive = json!({
+            "attributes": { "POSITION": positions, "NORMAL": normals },
+            "indices": indices,
+            "material": material,
+        });
+        match objects
+            .iter_mut()
+            .find(|(object, _)| *object == part.object)
+        {
+            Some((_, primitives)) => primitives.push(primitive),
+            None => objects.push((part.object.as_str(), vec![primitive])),
+        }
+    }
+
+    let meshes: Vec<Value> = objects
+        .iter()
+        .map(|(name, primitives)| json!({ "name": name, "primitives": primitives }))
+        .collect();
+    let nodes: Vec<Value> = objects
+        .iter()
+        .enumerate()
+        .map(|(index, (name, _))| json!({ "name": name, "mesh": index }))
+        .collect();
+    let gltf = json!({
+        "asset": { "version": "2.0", "generator": "design_to_blocks" },
+        "scene": 0,
+        "scenes": [{ "nodes": (0..nodes.len()).collect::<Vec<_>>() }],
+        "nodes": nodes,
+        "meshes": meshes,
+        "materials": materials.into_iter().map(|(_, material)| material).collect::<Vec<_>>(),
+        "accessors": accessors,
+        "bufferViews": views,
+        "buffers": [{
+            "uri": bin.file_name().unwrap_or_default().to_string_lossy(),
+            "byteLength": buffer.len(),
+        }],
+    });
+
+    fs::write(&bin, buffer)?;
+    fs::write(file, serde_json::to_string_pretty(&gltf)?)
+}
+
+pub fn export_geometry(
+    mut geometry: ResMut<GeometryExport>,
+    sim_rng: Res<SimRng>,
+    chunk_states: Res<ChunkStates>,
+    meshes: Res<Assets<Mesh>>,
+    materials: Res<Assets<StandardMaterial>>,
+    cubes: Query<(&AutoCube, &GlobalTransform, &Handle<StandardMaterial>)>,
+    outlines: Query<(&Handle<Mesh>, &GlobalTransform, &Handle<StandardMaterial>), With<Outline>>,
+) {
+    if !geometry.requested {
+        return;
+    }
+    geometry.requested = false;
+
+    let mut parts = Vec::new();
+    for cube in collect_cubes(&cubes, &materials) {
+        let roughness = chunk_states
+            .0
+            .get(cube.index)
+            .map_or(0.5, |chunk| chunk.perceptual_roughness);
+        part(
+            &mut parts,
+            format!("chunk_{}", cube.index),
+            cube.color,
+            roughness,
+        )
+        .add_cube(&cube);
+    }
+    if geometry.outlines {
+        for (mesh, transform, material) in outlines.iter() {
+            let Some(mesh) = meshes.get(mesh) else {
+                continue;
+            };
+            let (color, roughness) = materials.get(material).map_or((Color::BLACK, 0.5), |m| {
+                (m.base_color, m.perceptual_roughness)
+            });
+            // the stroke color is in the vertex colors, the material is white
+            let color = match vertex_color(mesh) {
+                Some(stroke) => stroke * color.as_linear_rgba_f32(),
+                None => color,
+            };
+            part(&mut parts, "outlines".into(), color, roughness).add_mesh(mesh, transform);
+        }
+    }
+    if parts.is_empty() {
+        warn!("nothing to export, there are no cubes");
+        return;
+    }
+    // keeps each object's parts together for obj
+    parts.sort_by(|a, b| a.object.cmp(&b.object));
+    for part in parts.iter_mut() {
+        for position in part.positions.iter_mut() {
+            *position *= geometry.unit_scale;
+        }
+    }
+
+    let file = Path::new(GEOMETRY_DIR)
+        .join(capture_name(sim_rng.seed))
+        .with_extension(geometry.format.extension());
+    let written = fs::create_dir_all(GEOMETRY_DIR).and_then(|_| match geometry.format {
+        GeometryFormat::Obj => write_obj(&file, &parts),
+        GeometryFormat::Gltf => write_gltf(&file, &parts),
+        GeometryFormat::Stl => write_stl(&file, &parts),
+    });
+    match written {
+        Ok(_) => println!("exported {:?}", file),
+        Err(err) => warn!("could not write {:?}: {}", file, err),
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use bevy::prelude::*;
 use grid::Grid;
 use rand::Rng;
@@ -7,17 +9,20 @@ use rand::Rng;
 // -- Create a new grid with size
 // -- Grid will have a clock
 // -- Grid will have an event dispatcher
+// -- a 3d grid is a stack of 2d grids, one layer per z step
 //
 //
+#[derive(Default, Clone)]
 pub struct GridBlock {
     pub occupied: bool,
 }
 
 pub struct GridMaster {
-    grid: Grid<GridBlock>,
+    layers: Vec<Grid<GridBlock>>,
     clock: Clock,
 }
 
+#[derive(Clone)]
 pub struct Clock {
     time: f32,
     interval: f32,
@@ -108,6 +113,107 @@ impl GridMasterTrait for Grid<GridBlock> {
     }
 }
 
+impl GridMaster {
+    pub fn new(width: usize, height: usize, depth: usize) -> Self {
+        GridMaster {
+            layers: (0..depth.max(1))
+                .map(|_| Grid::new(width.max(1), height.max(1)))
+                .collect(),
+            clock: Clock::new(),
+        }
+    }
+
+    pub fn size(&self) -> (usize, usize, usize) {
+        let (width, height) = self.layers[0].size();
+        (width, height, self.layers.len())
+    }
+
+    pub fn is_occupied(&self, x: usize, y: usize, z: usize) -> bool {
+        self.layers
+            .get(z)
+            .and_then(|layer| layer.get(x, y))
+            .is_some_and(|block| block.occupied)
+    }
+
+    pub fn set_occupied(&mut self, x: usize, y: usize, z: usize, occupied: bool) {
+        if let Some(block) = self.layers.get_mut(z).and_then(|layer| layer.get_mut(x, y)) {
+            block.occupied = occupied;
+        }
+    }
+
+    pub fn occupied(&self) -> Vec<(usize, usize, usize)> {
+        let (width, height, depth) = self.size();
+        let mut vec = Vec::new();
+        for z in 0..depth {
+            for y in 0..height {
+                for x in 0..width {
+                    if self.is_occupied(x, y, z) {
+                        vec.push((x, y, z));
+                    }
+                }
+            }
+        }
+        vec
+    }
+
+    // occupied cells around x, y, z
+    // 8 neighbours on a flat grid, 26 when there is more than one layer
+    pub fn occupied_neighbours(&self, x: usize, y: usize, z: usize) -> u8 {
+        let (width, height, depth) = self.size();
+        let mut count = 0;
+        for dz in -1..=1_i32 {
+            for dy in -1..=1_i32 {
+                for dx in -1..=1_i32 {
+                    if dx == 0 && dy == 0 && dz == 0 {
+                        continue;
+                    }
+                    let (nx, ny, nz) = (x as i32 + dx, y as i32 + dy, z as i32 + dz);
+                    if nx < 0 || ny < 0 || nz < 0 {
+                        continue;
+                    }
+                    let (nx, ny, nz) = (nx as usize, ny as usize, nz as usize);
+                    if nx < width && ny < height && nz < depth && self.is_occupied(nx, ny, nz) {
+                        count += 1;
+                    }
+                }
+            }
+        }
+        count
+    }
+
+    // advance every cell at once, `rule` gets (occupied, neighbours) and
+    // returns whether the cell is occupied next step
+    // returns the cells that were born and the ones that died
+    pub fn step(
+        &mut self,
+        rule: impl Fn(bool, u8) -> bool,
+    ) -> (Vec<(usize, usize, usize)>, HashSet<(usize, usize, usize)>) {
+        let (width, height, depth) = self.size();
+        let mut born = Vec::new();
+        let mut died = HashSet::new();
+        for z in 0..depth {
+            for y in 0..height {
+                for x in 0..width {
+                    let occupied = self.is_occupied(x, y, z);
+                    let next = rule(occupied, self.occupied_neighbours(x, y, z));
+                    if next && !occupied {
+                        born.push((x, y, z));
+                    } else if !next && occupied {
+                        died.insert((x, y, z));
+                    }
+                }
+            }
+        }
+        for &(x, y, z) in born.iter() {
+            self.set_occupied(x, y, z, true);
+        }
+        for &(x, y, z) in died.iter() {
+            self.set_occupied(x, y, z, false);
+        }
+        (born, died)
+    }
+}
+
 impl Clock {
     pub fn new() -> Self {
         Clock {
@@ -124,6 +230,16 @@ impl Clock {
         self.time += dt;
     }
 
+    // true once per elapsed interval, keeps the remainder
+    pub fn finished(&mut self) -> bool {
+        if self.interval > 0.0 && self.time >= self.interval {
+            self.time -= self.interval;
+            true
+        } else {
+            false
+        }
+    }
+
     pub fn reset(&mut self) {
         self.time = 0.0;
     }
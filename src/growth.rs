@@ -0,0 +1,499 @@
+use std::{cell::OnceCell, collections::HashMap};
+
+use bevy::prelude::*;
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::{region::Region, ChunkState};
+// a growth rule decides where a block that reached full life
+// spawns its children
+// -- every chunk picks one rule and its parameters
+// -- positions are in world units, bounds are multiplied by the chunk scale
+// -- children stay in the chunk's region, its bounds box unless it has one
+// -- `cluster` is every live block of the same chunk (used by dla)
+
+pub trait GrowthRule {
+    fn children(
+        &self,
+        cur: Vec3,
+        chunk: &ChunkState,
+        cluster: &Cluster,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Vec3>;
+}
+
+// live blocks of a chunk, hashed into cells on the first distance test
+#[derive(Default)]
+pub struct Cluster {
+    pub points: Vec<Vec3>,
+    // cell size and the points of every cell
+    cells: OnceCell<(f32, HashMap<IVec3, Vec<Vec3>>)>,
+}
+
+impl Cluster {
+    pub fn new(points: Vec<Vec3>) -> Self {
+        Cluster {
+            points,
+            cells: OnceCell::new(),
+        }
+    }
+
+    // whether a block is within `distance` of the point, the cells are as
+    // big as the first distance asked for, a chunk always asks the same
+    pub fn touches(&self, point: Vec3, distance: f32) -> bool {
+        let (size, cells) = self.cells.get_or_init(|| {
+            let size = distance.max(f32::EPSILON);
+            let mut cells: HashMap<IVec3, Vec<Vec3>> = HashMap::new();
+            for point in &self.points {
+                cells.entry(cell(*point, size)).or_default().push(*point);
+            }
+            (size, cells)
+        });
+        let reach = (distance / size).ceil() as i32;
+        let center = cell(point, *size);
+        for x in -reach..=reach {
+            for y in -reach..=reach {
+                for z in -reach..=reach {
+                    let near = cells.get(&(center + IVec3::new(x, y, z)));
+                    if near.is_some_and(|near| near.iter().any(|c| c.distance(point) <= distance)) {
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+}
+
+fn cell(point: Vec3, size: f32) -> IVec3 {
+    (point / size).floor().as_ivec3()
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub enum Growth {
+    RandomWalk(RandomWalk),
+    Branching(Branching),
+    Drift(Drift),
+    Attract(Attract),
+    Stack(Stack),
+    Dla(Dla),
+}
+
+impl Growth {
+    pub fn rule(&self) -> &dyn GrowthRule {
+        match self {
+            Growth::RandomWalk(r) => r,
+            Growth::Branching(r) => r,
+            Growth::Drift(r) => r,
+            Growth::Attract(r) => r,
+            Growth::Stack(r) => r,
+            Growth::Dla(r) => r,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Growth::RandomWalk(_) => "Random walk",
+            Growth::Branching(_) => "Branching",
+            Growth::Drift(_) => "Drift",
+            Growth::Attract(_) => "Attract",
+            Growth::Stack(_) => "Stack",
+            Growth::Dla(_) => "DLA",
+        }
+    }
+
+    // one of each, used to fill the rule picker
+    pub fn all() -> Vec<Growth> {
+        vec![
+            Growth::RandomWalk(RandomWalk::default()),
+            Growth::Branching(Branching::default()),
+            Growth::Drift(Drift::default()),
+            Growth::Attract(Attract::default()),
+            Growth::Stack(Stack::default()),
+            Growth::Dla(Dla::default()),
+        ]
+    }
+}
+
+impl Default for Growth {
+    fn default() -> Self {
+        Growth::RandomWalk(RandomWalk::default())
+    }
+}
+
+// the original rule: one child somewhere around the parent
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct RandomWalk {
+    pub step: f32,
+}
+
+impl Default for RandomWalk {
+    fn default() -> Self {
+        RandomWalk { step: 0.4 }
+    }
+}
+
+impl GrowthRule for RandomWalk {
+    fn children(
+        &self,
+        cur: Vec3,
+        chunk: &ChunkState,
+        _cluster: &Cluster,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Vec3> {
+        vec![get_random_direction(
+            cur,
+            &chunk.region(),
+            chunk.scale,
+            self.step,
+            rng,
+        )]
+    }
+}
+
+// n children per parent, every one on its own random walk
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Branching {
+    pub children: usize,
+    pub step: f32,
+}
+
+impl Default for Branching {
+    fn default() -> Self {
+        Branching {
+            children: 2,
+            step: 0.4,
+        }
+    }
+}
+
+impl GrowthRule for Branching {
+    fn children(
+        &self,
+        cur: Vec3,
+        chunk: &ChunkState,
+        _cluster: &Cluster,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Vec3> {
+        let region = chunk.region();
+        (0..self.children)
+            .map(|_| get_random_direction(cur, &region, chunk.scale, self.step, rng))
+            .collect()
+    }
+}
+
+// random walk with a constant pull, gravity is drift along -y
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Drift {
+    pub direction: Vec3,
+    pub strength: f32,
+    pub step: f32,
+}
+
+impl Default for Drift {
+    fn default() -> Self {
+        Drift {
+            direction: Vec3::NEG_Y,
+            strength: 0.2,
+            step: 0.4,
+        }
+    }
+}
+
+impl GrowthRule for Drift {
+    fn children(
+        &self,
+        cur: Vec3,
+        chunk: &ChunkState,
+        _cluster: &Cluster,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Vec3> {
+        let pull = self.direction.normalize_or_zero() * self.strength * chunk.scale;
+        vec![get_random_direction(
+            cur + pull,
+            &chunk.region(),
+            chunk.scale,
+            self.step,
+            rng,
+        )]
+    }
+}
+
+// random walk that closes in on a target point (in bounds units)
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attract {
+    pub target: Vec3,
+    pub strength: f32,
+    pub step: f32,
+}
+
+impl Default for Attract {
+    fn default() -> Self {
+        Attract {
+            target: Vec3::ZERO,
+            strength: 0.3,
+            step: 0.4,
+        }
+    }
+}
+
+impl GrowthRule for Attract {
+    fn children(
+        &self,
+        cur: Vec3,
+        chunk: &ChunkState,
+        _cluster: &Cluster,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Vec3> {
+        let target = self.target * chunk.scale;
+        let towards = cur.lerp(target, self.strength.clamp(0.0, 1.0));
+        vec![get_random_direction(
+            towards,
+            &chunk.region(),
+            chunk.scale,
+            self.step,
+            rng,
+        )]
+    }
+}
+
+// children go on top of the parent along an axis, makes columns
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Stack {
+    pub axis: Vec3,
+    pub spacing: f32,
+    pub jitter: f32,
+}
+
+impl Default for Stack {
+    fn default() -> Self {
+        Stack {
+            axis: Vec3::Y,
+            spacing: 0.3,
+            jitter: 0.02,
+        }
+    }
+}
+
+impl GrowthRule for Stack {
+    fn children(
+        &self,
+        cur: Vec3,
+        chunk: &ChunkState,
+        _cluster: &Cluster,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Vec3> {
+        let next = cur + self.axis.normalize_or_zero() * self.spacing * chunk.scale;
+        vec![get_random_direction(
+            next,
+            &chunk.region(),
+            chunk.scale,
+            self.jitter,
+            rng,
+        )]
+    }
+}
+
+// diffusion limited aggregation
+// a walker is released somewhere in the region and wanders `step` at a time
+// until it is within `stick` of the cluster, if it never is nothing is spawned
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Dla {
+    pub walkers: usize,
+    pub steps: usize,
+    pub step: f32,
+    pub stick: f32,
+}
+
+impl Default for Dla {
+    fn default() -> Self {
+        Dla {
+            walkers: 1,
+            steps: 200,
+            step: 0.4,
+            stick: 0.3,
+        }
+    }
+}
+
+impl GrowthRule for Dla {
+    fn children(
+        &self,
+        _cur: Vec3,
+        chunk: &ChunkState,
+        cluster: &Cluster,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Vec3> {
+        let region = chunk.region();
+        let stick = self.stick * chunk.scale;
+        let mut v = Vec::new();
+
+        for _ in 0..self.walkers {
+            let Some(mut walker) = region.sample(rng).map(|point| point * chunk.scale) else {
+                break;
+            };
+            for _ in 0..self.steps {
+                if cluster.touches(walker, stick) {
+                    v.push(walker);
+                    break;
+                }
+                walker = get_random_direction(walker, &region, chunk.scale, self.step, rng);
+            }
+        }
+        v
+    }
+}
+
+// a step of up to `step` bounds units along every axis, kept in the region
+pub fn get_random_direction(
+    cur: Vec3,
+    region: &Region,
+    scale: f32,
+    step: f32,
+    rng: &mut dyn RngCore,
+) -> Vec3 {
+    let x = get_random_f32(cur.x, scale, step, rng);
+    let y = get_random_f32(cur.y, scale, step, rng);
+    let z = get_random_f32(cur.z, scale, step, rng);
+    region.constrain(cur / scale, Vec3::new(x, y, z) / scale, rng) * scale
+}
+
+fn get_random_f32(c: f32, scale: f32, step: f32, rng: &mut dyn RngCore) -> f32 {
+    let range: f32 = match step > 0.0 {
+        true => rng.gen_range(-step * scale..step * scale),
+        false => 0.0,
+    };
+    range + c
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::Bounds;
+
+    fn chunk(growth: Growth) -> ChunkState {
+        ChunkState {
+            bounds: Bounds {
+                min: Vec3::new(-2., -1., 0.),
+                max: Vec3::new(2., 1., 0.5),
+            },
+            growth,
+            ..default()
+        }
+    }
+
+    // in the bounds, give or take the rounding of the scale
+    fn inside(chunk: &ChunkState, point: Vec3) -> bool {
+        let point = point / chunk.scale;
+        point.cmpge(chunk.bounds.min - 1e-4).all() && point.cmple(chunk.bounds.max + 1e-4).all()
+    }
+
+    // children of 50 generations, every one from the last child
+    fn grow(chunk: &ChunkState, cluster: &Cluster, counts: &mut Vec<usize>) -> Vec<Vec3> {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut cur = Vec3::new(1.9, 0.9, 0.4) * chunk.scale;
+        let mut all = Vec::new();
+        for _ in 0..50 {
+            let children = chunk.growth.rule().children(cur, chunk, cluster, &mut rng);
+            counts.push(children.len());
+            cur = children.last().copied().unwrap_or(cur);
+            all.extend(children);
+        }
+        all
+    }
+
+    #[test]
+    fn rules_spawn_their_children_in_bounds() {
+        let rules = [
+            (Growth::RandomWalk(RandomWalk { step: 1.5 }), 1),
+            (
+                Growth::Branching(Branching {
+                    children: 3,
+                    step: 1.5,
+                }),
+                3,
+            ),
+            (
+                Growth::Drift(Drift {
+                    direction: Vec3::X,
+                    strength: 2.,
+                    step: 0.4,
+                }),
+                1,
+            ),
+            (
+                Growth::Attract(Attract {
+                    target: Vec3::new(10., 10., 10.),
+                    strength: 1.,
+                    step: 0.4,
+                }),
+                1,
+            ),
+            (
+                Growth::Stack(Stack {
+                    axis: Vec3::Y,
+                    spacing: 0.8,
+                    jitter: 0.1,
+                }),
+                1,
+            ),
+        ];
+        for (growth, count) in rules {
+            let chunk = chunk(growth);
+            let mut counts = Vec::new();
+            let children = grow(&chunk, &Cluster::default(), &mut counts);
+            assert!(
+                counts.iter().all(|c| *c == count),
+                "{}",
+                chunk.growth.name()
+            );
+            assert!(
+                children.iter().all(|child| inside(&chunk, *child)),
+                "{}",
+                chunk.growth.name()
+            );
+        }
+    }
+
+    #[test]
+    fn dla_sticks_to_the_cluster_in_bounds() {
+        let chunk = chunk(Growth::Dla(Dla {
+            walkers: 3,
+            steps: 200,
+            step: 0.4,
+            stick: 0.3,
+        }));
+        // a cluster all over the bounds, every walker sticks right away
+        let points = (0..=8)
+            .flat_map(|x| {
+                (0..=4).map(move |y| Vec3::new(x as f32 / 2. - 2., y as f32 / 2. - 1., 0.25))
+            })
+            .map(|point| point * chunk.scale)
+            .collect();
+        let cluster = Cluster::new(points);
+        let mut counts = Vec::new();
+        let children = grow(&chunk, &cluster, &mut counts);
+        assert!(counts.iter().all(|c| *c == 3));
+        assert!(children.iter().all(|child| inside(&chunk, *child)));
+        assert!(children
+            .iter()
+            .all(|child| cluster.touches(*child, 0.3 * chunk.scale)));
+
+        // nothing to stick to
+        let mut counts = Vec::new();
+        assert!(grow(&chunk, &Cluster::default(), &mut counts).is_empty());
+    }
+
+    #[test]
+    fn cluster_touches_across_cells() {
+        let cluster = Cluster::new(vec![Vec3::new(0.95, 0., 0.), Vec3::new(-5., -5., -5.)]);
+        assert!(cluster.touches(Vec3::new(1.05, 0., 0.), 0.2));
+        assert!(cluster.touches(Vec3::new(0.9, 0.15, 0.), 0.2));
+        assert!(!cluster.touches(Vec3::new(1.2, 0., 0.), 0.2));
+        assert!(cluster.touches(Vec3::new(-5.1, -4.9, -5.), 0.2));
+        assert!(!Cluster::default().touches(Vec3::ZERO, 1.));
+    }
+}
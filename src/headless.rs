@@ -0,0 +1,255 @@
+use std::{
+    collections::VecDeque,
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use bevy::{app::AppExit, prelude::*};
+
+use crate::{
+    batch::Variation,
+    export::{Capture, ExportSettings},
+    setup::PlisCamera,
+    simulation::{Reset, SimRng, Simulation},
+    ChunkStates,
+};
+// offline rendering from the command line, no window and no input
+// -- the scene file is a capture sidecar, or hand written in that format
+// -- the composition is reset and grown to the scene's `tick`, the step it
+//    was captured at, or to `--ticks` steps, `tick_seconds` virtual seconds a
+//    frame, so a sidecar renders the still it came with
+// -- growing stops early when no chunk can step anymore
+// -- then a still is captured the usual way, passes included
+// -- batch renders every variation of a sweep over the scene one after
+//    the other, see batch.rs, the app exits after the last one
+// -- the exit status is 1 when any still was never written, main checks
+//    `failures` once the app and the export threads are done
+
+pub const USAGE: &str = "usage: design_to_blocks render <scene.json> [options]
+       design_to_blocks batch <scene.json> <sweep.json> [options]
+       design_to_blocks sheet <dir> [--columns N] [--width PX] [--out FILE]
+       design_to_blocks import <layout.svg|layout.json> [--unit PX] [--scene FILE] [--out FILE]
+       design_to_blocks text <text> <font.ttf> [--per glyph|word] [--size UNITS] [--spacing EM]
+           [--line-height EM] [--depth UNITS] [--scene FILE] [--out FILE]
+options: [--ticks N] [--seed S] [--tick-seconds T] [--warmup FRAMES] [--out DIR]";
+
+#[derive(Clone, Debug)]
+pub struct RenderArgs {
+    pub scene: PathBuf,
+    // sweep file of the batch command
+    pub batch: Option<PathBuf>,
+    // overrides the tick of the scene
+    pub ticks: Option<u64>,
+    // overrides the seed of the scene
+    pub seed: Option<u64>,
+    pub tick_seconds: f32,
+    pub warmup: u32,
+    // folder for the stills and their sidecars, out/stills or
+    // out/batches/<timestamp>_seed<seed> when not given
+    pub out: Option<PathBuf>,
+}
+
+impl RenderArgs {
+    // None when there are no arguments and the app should start interactively
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<RenderArgs>, String> {
+        let batch = match args.next().as_deref() {
+            None => return Ok(None),
+            Some("render") => false,
+            Some("batch") => true,
+            Some(other) => return Err(format!("unknown command {}", other)),
+        };
+        let scene = args.next().ok_or("missing scene file")?;
+        let sweep = match batch {
+            true => Some(args.next().ok_or("missing sweep file")?.into()),
+            false => None,
+        };
+        let mut render = RenderArgs {
+            scene: scene.into(),
+            batch: sweep,
+            ticks: None,
+            seed: None,
+            tick_seconds: 0.8,
+            warmup: 30,
+            out: None,
+        };
+        while let Some(flag) = args.next() {
+            let value = args.next().ok_or(format!("missing value for {}", flag))?;
+            match flag.as_str() {
+                "--ticks" => render.ticks = Some(parse_option(&flag, &value)?),
+                "--seed" => render.seed = Some(parse_option(&flag, &value)?),
+                "--tick-seconds" => render.tick_seconds = parse_option(&flag, &value)?,
+                "--warmup" => render.warmup = parse_option(&flag, &value)?,
+                "--out" => render.out = Some(value.into()),
+                _ => return Err(format!("unknown option {}", flag)),
+            }
+        }
+        Ok(Some(render))
+    }
+}
+
+pub fn parse_option<T: FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value {} for {}", value, flag))
+}
+
+#[derive(Clone, Copy)]
+enum HeadlessState {
+    Next,
+    // steps to grow to
+    Grow(u64),
+    // stills written before this capture
+    Capture(usize),
+    Done,
+}
+
+#[derive(Resource)]
+pub struct Headless {
+    args: RenderArgs,
+    // scenes still to render
+    jobs: VecDeque<Variation>,
+    state: HeadlessState,
+    // captures that didn't write a still
+    failed: Arc<AtomicUsize>,
+}
+
+impl Headless {
+    pub fn new(args: RenderArgs, jobs: Vec<Variation>) -> Self {
+        Headless {
+            args,
+            jobs: jobs.into(),
+            state: HeadlessState::Next,
+            failed: default(),
+        }
+    }
+
+    pub fn failures(&self) -> Arc<AtomicUsize> {
+        self.failed.clone()
+    }
+}
+
+pub fn headless_render(
+    mut headless: ResMut<Headless>,
+    mut simulation: ResMut<Simulation>,
+    mut capture: ResMut<Capture>,
+    mut chunk_states: ResMut<ChunkStates>,
+    mut export_settings: ResMut<ExportSettings>,
+    mut sim_rng: ResMut<SimRng>,
+    mut camera: Query<(&mut Transform, &mut Projection), With<PlisCamera>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    headless.state = match headless.state {
+        HeadlessState::Next => {
+            let Ok((mut transform, mut projection)) = camera.get_single_mut() else {
+                return;
+            };
+            let Some(job) = headless.jobs.pop_front() else {
+                let failed = headless.failed.load(Ordering::Relaxed);
+                if failed > 0 {
+                    error!("{} stills were never written", failed);
+                }
+                exit.send(AppExit);
+                headless.state = HeadlessState::Done;
+                return;
+            };
+            let scene = job.scene;
+            scene.camera.apply(&mut transform, &mut projection);
+            chunk_states.0 = scene.chunks;
+            // a new export size recreates the export textures, only when it differs
+            if *export_settings != scene.export {
+                *export_settings = scene.export;
+            }
+            *sim_rng = SimRng::new(scene.seed);
+            simulation.paused = true;
+            simulation.reset = Some(Reset::All);
+            let ticks = headless.args.ticks.unwrap_or(scene.tick);
+            simulation.until = Some(ticks);
+
+            capture.name = job.name;
+            capture.parameters = job.parameters;
+            HeadlessState::Grow(ticks)
+        }
+        HeadlessState::Grow(ticks)
+            if simulation.tick < ticks && growing(&chunk_states, &simulation) =>
+        {
+            simulation.advance = headless.args.tick_seconds;
+            HeadlessState::Grow(ticks)
+        }
+        HeadlessState::Grow(ticks) => {
+            if simulation.tick < ticks {
+                warn!("stopped growing at tick {} of {}", simulation.tick, ticks);
+            }
+            capture.warmup = headless.args.warmup;
+            if let Some(out) = &headless.args.out {
+                capture.dir = out.clone();
+            }
+            capture.requested = true;
+            HeadlessState::Capture(capture.written)
+        }
+        HeadlessState::Capture(written) if capture.requested || capture.busy() => {
+            HeadlessState::Capture(written)
+        }
+        HeadlessState::Capture(written) => {
+            if capture.written == written {
+                headless.failed.fetch_add(1, Ordering::Relaxed);
+            }
+            HeadlessState::Next
+        }
+        HeadlessState::Done => HeadlessState::Done,
+    };
+}
+
+// whether a chunk is still playing and hasn't settled
+fn growing(chunk_states: &ChunkStates, simulation: &Simulation) -> bool {
+    chunk_states
+        .0
+        .iter()
+        .enumerate()
+        .any(|(index, chunk)| chunk.playing && !simulation.population(index).settled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Option<RenderArgs>, String> {
+        RenderArgs::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn no_arguments_is_interactive() {
+        assert!(parse(&[]).unwrap().is_none());
+    }
+
+    #[test]
+    fn parses_render_and_batch() {
+        let render = parse(&["render", "scene.json", "--ticks", "5", "--seed", "3"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(render.scene, PathBuf::from("scene.json"));
+        assert_eq!(render.batch, None);
+        assert_eq!(render.ticks, Some(5));
+        assert_eq!(render.seed, Some(3));
+        assert_eq!(render.warmup, 30);
+
+        let batch = parse(&["batch", "scene.json", "sweep.json", "--out", "stills"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(batch.batch, Some(PathBuf::from("sweep.json")));
+        assert_eq!(batch.out, Some(PathBuf::from("stills")));
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(parse(&["draw"]).is_err());
+        assert!(parse(&["render"]).is_err());
+        assert!(parse(&["batch", "scene.json"]).is_err());
+        assert!(parse(&["render", "scene.json", "--frames", "5"]).is_err());
+        assert!(parse(&["render", "scene.json", "--ticks"]).is_err());
+        assert!(parse(&["render", "scene.json", "--ticks", "many"]).is_err());
+    }
+}
@@ -0,0 +1,187 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{mask::Masks, AutoCube, ChunkState, ChunkStates};
+// heightmap mode for a chunk, a block field out of a grayscale image
+// -- the xy rectangle of the bounds is cut into cells of `cell` size and
+//    the image is stretched over it like a mask, top of the image at max y
+// -- the brightness of the pixel under a cell's centre, quantised to
+//    `levels` steps, is how many cells tall its stack is along z, or how far
+//    out along z its one cube sits, so every height is a multiple of the cell
+// -- the field doesn't grow, it's built once and again when the image or
+//    the settings change or the chunk is reset, cubes the chunk grew before
+//    are cleared with it
+// -- a field whose image can't be read isn't built, it's tried again until
+//    the image turns up
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum HeightMode {
+    Stack,
+    Offset,
+}
+
+impl HeightMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HeightMode::Stack => "Stack",
+            HeightMode::Offset => "Offset",
+        }
+    }
+
+    pub fn all() -> [HeightMode; 2] {
+        [HeightMode::Stack, HeightMode::Offset]
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Heightmap {
+    // grayscale image in the assets folder
+    pub image: String,
+    // size of a cell in bounds units
+    pub cell: f32,
+    // cells for white
+    pub levels: usize,
+    pub mode: HeightMode,
+}
+
+impl Default for Heightmap {
+    fn default() -> Self {
+        Heightmap {
+            image: String::new(),
+            cell: 0.25,
+            levels: 8,
+            mode: HeightMode::Stack,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct FieldCell;
+
+// what a chunk's field was built from
+#[derive(Clone, PartialEq)]
+pub struct Built {
+    heightmap: Heightmap,
+    min: Vec3,
+    max: Vec3,
+    scale: f32,
+    base_color: Color,
+    perceptual_roughness: f32,
+}
+
+impl Built {
+    fn of(chunk: &ChunkState, heightmap: &Heightmap) -> Self {
+        Built {
+            heightmap: heightmap.clone(),
+            min: chunk.bounds.min,
+            max: chunk.bounds.max,
+            scale: chunk.scale,
+            base_color: chunk.base_color,
+            perceptual_roughness: chunk.perceptual_roughness,
+        }
+    }
+}
+
+// one per chunk, None until the chunk's field is built
+#[derive(Resource, Default)]
+pub struct HeightFields(pub Vec<Option<Built>>);
+
+// cube centres of the field in world units, None when the image can't be read
+fn field(chunk: &ChunkState, heightmap: &Heightmap, masks: &mut Masks) -> Option<Vec<Vec3>> {
+    let image = masks.image(&heightmap.image)?;
+    let min = chunk.bounds.min.min(chunk.bounds.max);
+    let max = chunk.bounds.min.max(chunk.bounds.max);
+    let cell = heightmap.cell.max(0.01);
+    let (width, height) = (
+        ((max.x - min.x) / cell).ceil().max(1.) as usize,
+        ((max.y - min.y) / cell).ceil().max(1.) as usize,
+    );
+
+    let mut cubes = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            let center = Vec3::new(
+                min.x + (x as f32 + 0.5) * cell,
+                min.y + (y as f32 + 0.5) * cell,
+                min.z,
+            ) * chunk.scale;
+            let Some(color) = image.color(chunk, center) else {
+                continue;
+            };
+            let [r, g, b, _] = color.as_rgba_f32();
+            let brightness = r * 0.299 + g * 0.587 + b * 0.114;
+            let level = (brightness * heightmap.levels as f32).round() as usize;
+            let at = |step: usize| center + Vec3::Z * (step as f32 + 0.5) * cell * chunk.scale;
+            match heightmap.mode {
+                HeightMode::Stack => cubes.extend((0..level).map(at)),
+                HeightMode::Offset => cubes.push(at(level)),
+            }
+        }
+    }
+    Some(cubes)
+}
+
+pub fn update_heightmaps(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    chunk_states: Res<ChunkStates>,
+    mut masks: ResMut<Masks>,
+    mut fields: ResMut<HeightFields>,
+    blocks: Query<(Entity, &AutoCube, Option<&FieldCell>)>,
+) {
+    let fields = &mut fields.0;
+    fields.resize_with(chunk_states.0.len(), || None);
+
+    for (index, chunk) in chunk_states.0.iter().enumerate() {
+        let built = chunk
+            .heightmap
+            .as_ref()
+            .map(|heightmap| Built::of(chunk, heightmap));
+        if fields[index] == built {
+            continue;
+        }
+        // left heightmap mode or the field is out of date, in heightmap mode
+        // grown cubes go as well
+        for (entity, block, cell) in blocks.iter() {
+            if block.index == index && (cell.is_some() || built.is_some()) {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+        let Some(heightmap) = &chunk.heightmap else {
+            fields[index] = None;
+            continue;
+        };
+        let Some(cubes) = field(chunk, heightmap, &mut masks) else {
+            fields[index] = None;
+            continue;
+        };
+        fields[index] = built;
+
+        let size = heightmap.cell.max(0.01) * chunk.scale * 0.9;
+        let mesh = meshes.add(Mesh::from(shape::Cube { size }));
+        let material = materials.add(StandardMaterial {
+            base_color: chunk.base_color,
+            perceptual_roughness: chunk.perceptual_roughness,
+            reflectance: 0.1,
+            ..default()
+        });
+        for translation in cubes {
+            commands.spawn((
+                PbrBundle {
+                    mesh: mesh.clone(),
+                    material: material.clone(),
+                    transform: Transform::from_translation(translation),
+                    ..default()
+                },
+                AutoCube {
+                    life_time: chunk.life_time,
+                    index,
+                    size,
+                    color: None,
+                },
+                FieldCell,
+            ));
+        }
+    }
+}
@@ -0,0 +1,621 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{headless::parse_option, scene::Scene, Bounds, ChunkState, Rect};
+// 2d layouts from a design tool as a scene, one chunk per rectangle
+//   design_to_blocks import <layout.svg|layout.json> [--unit PX] [--scene FILE] [--out FILE]
+// -- svg: every rendered <rect> with x, y, width, height and a fill, from
+//    the attribute or the style, rects in defs, clip paths, masks, patterns
+//    and symbols are left out
+// -- translate and scale transforms of the rects and their groups are
+//    applied, any other transform is an error
+// -- json: { "unit": 100, "height": 800, "rects": [
+//              { "x": 0, "y": 0, "w": 120, "h": 300, "fill": "#e0401a",
+//                "z": 0, "depth": 0.5, "layer": "depth 1" } ] }
+// -- `unit` layout units make one bounds unit, layouts are y down so they
+//    are flipped against the document height to stand the right way up
+// -- a rect goes from z to z + depth in bounds units, taken from the rect's
+//    own data-z / data-depth (z / depth in json) or else from its layer,
+//    the id or label of the enclosing svg groups, like "z=1 depth=0.5" or
+//    just "0.5" for the depth
+// -- the other chunk settings come from the first chunk of the base scene,
+//    the base scene also gives camera, export and seed
+
+pub const DEFAULT_UNIT: f32 = 100.;
+
+#[derive(Clone, Debug)]
+pub struct ImportArgs {
+    pub layout: PathBuf,
+    // layout units per bounds unit, the document's own `unit` otherwise
+    pub unit: Option<f32>,
+    // base scene, the default composition when not given
+    pub scene: Option<PathBuf>,
+    // scene file to write, the layout's name with .scene.json when not given
+    pub out: Option<PathBuf>,
+}
+
+impl ImportArgs {
+    // the arguments after `import`
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<ImportArgs, String> {
+        let layout = args.next().ok_or("missing layout file")?;
+        let mut import = ImportArgs {
+            layout: layout.into(),
+            unit: None,
+            scene: None,
+            out: None,
+        };
+        while let Some(flag) = args.next() {
+            let value = args.next().ok_or(format!("missing value for {}", flag))?;
+            match flag.as_str() {
+                "--unit" => import.unit = Some(parse_option(&flag, &value)?),
+                "--scene" => import.scene = Some(value.into()),
+                "--out" => import.out = Some(value.into()),
+                _ => return Err(format!("unknown option {}", flag)),
+            }
+        }
+        Ok(import)
+    }
+}
+
+// a rectangle of the layout, y down
+#[derive(Clone, Debug, Deserialize)]
+pub struct LayoutRect {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+    #[serde(default)]
+    pub fill: Option<String>,
+    #[serde(default)]
+    pub z: Option<f32>,
+    #[serde(default)]
+    pub depth: Option<f32>,
+    #[serde(default)]
+    pub layer: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Layout {
+    #[serde(default)]
+    pub unit: Option<f32>,
+    // y of the document bottom, its height unless the viewBox starts lower,
+    // the bottom of the lowest rect when missing
+    #[serde(default)]
+    pub height: Option<f32>,
+    pub rects: Vec<LayoutRect>,
+}
+
+impl Layout {
+    pub fn read(path: impl AsRef<Path>) -> Result<Layout, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("could not read {:?}: {}", path, err))?;
+        let svg = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("svg"));
+        match svg {
+            true => parse_svg(&text).map_err(|err| format!("could not parse {:?}: {}", path, err)),
+            false => serde_json::from_str(&text)
+                .map_err(|err| format!("could not parse {:?}: {}", path, err)),
+        }
+    }
+
+    // one chunk per rect, the rest of the settings from `template`
+    pub fn chunks(&self, unit: f32, template: &ChunkState) -> Vec<ChunkState> {
+        let unit = unit.max(f32::EPSILON);
+        let height = self.height.unwrap_or_else(|| {
+            self.rects
+                .iter()
+                .map(|rect| rect.y + rect.h)
+                .fold(0., f32::max)
+        });
+        self.rects
+            .iter()
+            .map(|rect| {
+                let layer = rect.layer.as_deref().map(layer_depth).unwrap_or_default();
+                let z = rect.z.or(layer.0).unwrap_or(0.);
+                let depth = rect.depth.or(layer.1).unwrap_or(0.);
+                let mut bounds: Bounds = Rect {
+                    x: rect.x / unit,
+                    y: (height - rect.y - rect.h) / unit,
+                    z,
+                    w: rect.w / unit,
+                    h: rect.h / unit,
+                }
+                .into();
+                bounds.max.z += depth;
+
+                let mut chunk = template.clone();
+                chunk.bounds = bounds;
+                // the template's region sits where the template was
+                chunk.region = None;
+                if let Some(fill) = rect.fill.as_deref() {
+                    match parse_color(fill) {
+                        Some(color) => chunk.base_color = color,
+                        None if fill.trim() != "none" => {
+                            eprintln!("unknown fill {}, keeping the template color", fill)
+                        }
+                        None => {}
+                    }
+                }
+                chunk
+            })
+            .collect()
+    }
+}
+
+// z and depth from a layer name, "z=1 depth=0.5", "depth 0.5" or "0.5"
+fn layer_depth(name: &str) -> (Option<f32>, Option<f32>) {
+    let mut z = None;
+    let mut depth = None;
+    let words: Vec<&str> = name
+        .split(|c: char| c.is_whitespace() || c == '=' || c == ':' || c == ',')
+        .filter(|word| !word.is_empty())
+        .collect();
+    for (i, word) in words.iter().enumerate() {
+        let value = words.get(i + 1).and_then(|value| value.parse().ok());
+        match word.to_lowercase().as_str() {
+            "z" => z = value.or(z),
+            "depth" => depth = value.or(depth),
+            _ => {}
+        }
+    }
+    if depth.is_none() && words.len() == 1 {
+        depth = words[0].parse().ok();
+    }
+    (z, depth)
+}
+
+// #rgb, #rrggbb, rgb(r, g, b) and the basic named colors,
+// anything else keeps the template color
+fn parse_color(fill: &str) -> Option<Color> {
+    let fill = fill.trim();
+    if let Some(hex) = fill.strip_prefix('#') {
+        return Color::hex(hex).ok();
+    }
+    let Some(channels) = fill
+        .strip_prefix("rgb(")
+        .and_then(|fill| fill.strip_suffix(')'))
+    else {
+        return named_color(fill);
+    };
+    let channels: Vec<u8> = channels
+        .split(',')
+        .filter_map(|channel| channel.trim().parse::<f32>().ok())
+        .map(|channel| channel.round().clamp(0., 255.) as u8)
+        .collect();
+    match channels[..] {
+        [r, g, b] => Some(Color::rgb_u8(r, g, b)),
+        _ => None,
+    }
+}
+
+fn named_color(name: &str) -> Option<Color> {
+    let hex = match name.to_lowercase().as_str() {
+        "black" => "000000",
+        "white" => "ffffff",
+        "red" => "ff0000",
+        "lime" => "00ff00",
+        "green" => "008000",
+        "blue" => "0000ff",
+        "yellow" => "ffff00",
+        "cyan" | "aqua" => "00ffff",
+        "magenta" | "fuchsia" => "ff00ff",
+        "gray" | "grey" => "808080",
+        "silver" => "c0c0c0",
+        "maroon" => "800000",
+        "olive" => "808000",
+        "navy" => "000080",
+        "purple" => "800080",
+        "teal" => "008080",
+        "orange" => "ffa500",
+        _ => return None,
+    };
+    Color::hex(hex).ok()
+}
+
+// a number with or without a unit, "12", "12.5px"
+fn number(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let end = value
+        .find(|c: char| !(c.is_ascii_digit() || matches!(c, '.' | '-' | '+' | 'e' | 'E')))
+        .unwrap_or(value.len());
+    value[..end].parse().ok()
+}
+
+// name="value" pairs of a tag
+fn attributes(tag: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut rest = tag;
+    while let Some(eq) = rest.find('=') {
+        let name = rest[..eq]
+            .rsplit(|c: char| c.is_whitespace())
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let after = rest[eq + 1..].trim_start();
+        let Some(quote) = after.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+            break;
+        };
+        let Some(end) = after[1..].find(quote) else {
+            break;
+        };
+        attributes.push((name, after[1..end + 1].to_string()));
+        rest = &after[end + 2..];
+    }
+    attributes
+}
+
+fn attribute<'a>(attributes: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+// `fill:` of a style attribute
+fn style_fill(style: &str) -> Option<&str> {
+    style.split(';').find_map(|declaration| {
+        let (key, value) = declaration.split_once(':')?;
+        (key.trim() == "fill").then_some(value.trim())
+    })
+}
+
+// an axis aligned svg transform, scale then translate
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Placement {
+    scale: Vec2,
+    translate: Vec2,
+}
+
+impl Placement {
+    const IDENTITY: Placement = Placement {
+        scale: Vec2::ONE,
+        translate: Vec2::ZERO,
+    };
+
+    // `inner` first, then this one
+    fn compose(self, inner: Placement) -> Placement {
+        Placement {
+            scale: self.scale * inner.scale,
+            translate: self.translate + self.scale * inner.translate,
+        }
+    }
+
+    fn apply(self, point: Vec2) -> Vec2 {
+        point * self.scale + self.translate
+    }
+}
+
+// a transform attribute, translate, scale and matrices without rotation or skew
+fn parse_transform(transform: &str) -> Result<Placement, String> {
+    let mut placement = Placement::IDENTITY;
+    let mut rest = transform.trim();
+    while !rest.is_empty() {
+        let (name, after) = rest
+            .split_once('(')
+            .ok_or(format!("invalid transform {}", transform))?;
+        let (values, after) = after
+            .split_once(')')
+            .ok_or(format!("invalid transform {}", transform))?;
+        let values: Vec<f32> = values
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|value| !value.is_empty())
+            .map(|value| value.parse().ok())
+            .collect::<Option<_>>()
+            .ok_or(format!("invalid transform {}", transform))?;
+        let next = match (name.trim(), &values[..]) {
+            ("translate", &[x]) => Placement {
+                scale: Vec2::ONE,
+                translate: Vec2::new(x, 0.),
+            },
+            ("translate", &[x, y]) => Placement {
+                scale: Vec2::ONE,
+                translate: Vec2::new(x, y),
+            },
+            ("scale", &[s]) => Placement {
+                scale: Vec2::splat(s),
+                translate: Vec2::ZERO,
+            },
+            ("scale", &[x, y]) => Placement {
+                scale: Vec2::new(x, y),
+                translate: Vec2::ZERO,
+            },
+            ("matrix", &[a, b, c, d, e, f]) if b == 0. && c == 0. => Placement {
+                scale: Vec2::new(a, d),
+                translate: Vec2::new(e, f),
+            },
+            _ => {
+                return Err(format!(
+                    "unsupported transform {}, only translate and scale are applied",
+                    transform
+                ))
+            }
+        };
+        placement = placement.compose(next);
+        rest = after.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+    }
+    Ok(placement)
+}
+
+// the end of a tag, the first `>` outside of quotes
+fn tag_end(tag: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in tag.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), _) if c == open => quote = None,
+            (None, '>') => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+// elements whose content is never drawn where it is
+const NOT_RENDERED: [&str; 5] = ["defs", "clipPath", "mask", "pattern", "symbol"];
+
+// rects of an svg, with the names of the groups around them as the layer
+fn parse_svg(svg: &str) -> Result<Layout, String> {
+    let mut layout = Layout {
+        unit: None,
+        height: None,
+        rects: Vec::new(),
+    };
+    // names and placements of the open groups
+    let mut groups: Vec<(Option<String>, Placement)> = Vec::new();
+    // depth of the not rendered elements the scanner is in
+    let mut hidden = 0;
+    let mut rest = svg;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+        // comments, processing instructions and cdata aren't tags
+        let skipped = [("<!--", "-->"), ("<?", "?>"), ("<![CDATA[", "]]>")]
+            .into_iter()
+            .find(|(open, _)| rest.starts_with(open));
+        if let Some((open, close)) = skipped {
+            let Some(end) = rest[open.len()..].find(close) else {
+                break;
+            };
+            rest = &rest[open.len() + end + close.len()..];
+            continue;
+        }
+        let Some(end) = tag_end(rest) else {
+            break;
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        let name = tag
+            .split(char::is_whitespace)
+            .next()
+            .unwrap_or_default()
+            .trim_end_matches('/');
+        let closed = tag.ends_with('/');
+        if NOT_RENDERED.contains(&name) {
+            hidden += usize::from(!closed);
+            continue;
+        }
+        if name
+            .strip_prefix('/')
+            .is_some_and(|name| NOT_RENDERED.contains(&name))
+        {
+            hidden = hidden.saturating_sub(1);
+            continue;
+        }
+        if hidden > 0 {
+            continue;
+        }
+        let attributes = attributes(tag);
+        let placement = match attribute(&attributes, "transform") {
+            Some(transform) => parse_transform(transform)?,
+            None => Placement::IDENTITY,
+        };
+        let parent = groups
+            .last()
+            .map_or(Placement::IDENTITY, |(_, placement)| *placement);
+        match name {
+            "svg" => {
+                // the bottom of the document, y runs from min-y down to min-y + height
+                let view_box_bottom = attribute(&attributes, "viewBox").and_then(|view_box| {
+                    let values: Vec<f32> = view_box
+                        .split(|c: char| c.is_whitespace() || c == ',')
+                        .filter(|value| !value.is_empty())
+                        .map(number)
+                        .collect::<Option<_>>()?;
+                    match values[..] {
+                        [_, min_y, _, height] => Some(min_y + height),
+                        _ => None,
+                    }
+                });
+                layout.height =
+                    view_box_bottom.or(attribute(&attributes, "height").and_then(number));
+            }
+            "g" if !closed => {
+                let name = attribute(&attributes, "data-depth")
+                    .map(|depth| format!("depth {}", depth))
+                    .or(attribute(&attributes, "inkscape:label").map(String::from))
+                    .or(attribute(&attributes, "id").map(String::from));
+                groups.push((name, parent.compose(placement)));
+            }
+            "/g" => {
+                groups.pop();
+            }
+            "rect" => {
+                let value = |name: &str| attribute(&attributes, name).and_then(number);
+                let fill = attribute(&attributes, "style")
+                    .and_then(style_fill)
+                    .or(attribute(&attributes, "fill"))
+                    .map(String::from);
+                // the innermost group with a name that says something
+                let layer = groups
+                    .iter()
+                    .rev()
+                    .filter_map(|(name, _)| name.as_ref())
+                    .find(|name| layer_depth(name) != (None, None))
+                    .cloned();
+                let placement = parent.compose(placement);
+                let corner = Vec2::new(value("x").unwrap_or(0.), value("y").unwrap_or(0.));
+                let size = Vec2::new(value("width").unwrap_or(0.), value("height").unwrap_or(0.));
+                let (a, b) = (placement.apply(corner), placement.apply(corner + size));
+                let (min, max) = (a.min(b), a.max(b));
+                layout.rects.push(LayoutRect {
+                    x: min.x,
+                    y: min.y,
+                    w: max.x - min.x,
+                    h: max.y - min.y,
+                    fill,
+                    z: value("data-z"),
+                    depth: value("data-depth"),
+                    layer,
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(layout)
+}
+
+// reads the layout and writes it as a scene over the base scene
+pub fn import_layout(args: &ImportArgs) -> Result<PathBuf, String> {
+    let layout = Layout::read(&args.layout)?;
+    if layout.rects.is_empty() {
+        return Err(format!("no rects in {:?}", args.layout));
+    }
+    let mut scene = Scene::base(args.scene.as_deref())?;
+    let template = scene.chunks.first().cloned().unwrap_or_default();
+    let unit = args.unit.or(layout.unit).unwrap_or(DEFAULT_UNIT);
+    scene.chunks = layout.chunks(unit, &template);
+
+    let out = args
+        .out
+        .clone()
+        .unwrap_or_else(|| args.layout.with_extension("scene.json"));
+    scene
+        .write(&out)
+        .map_err(|err| format!("could not write {:?}: {}", out, err))?;
+    println!("{} chunks", scene.chunks.len());
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_layer_names() {
+        assert_eq!(layer_depth("z=1 depth=0.5"), (Some(1.), Some(0.5)));
+        assert_eq!(layer_depth("Depth: 2"), (None, Some(2.)));
+        assert_eq!(layer_depth("0.5"), (None, Some(0.5)));
+        assert_eq!(layer_depth("background"), (None, None));
+        assert_eq!(layer_depth("layer 2"), (None, None));
+    }
+
+    #[test]
+    fn reads_numbers_with_units() {
+        assert_eq!(number("12"), Some(12.));
+        assert_eq!(number(" 12.5px"), Some(12.5));
+        assert_eq!(number("-3e1"), Some(-30.));
+        assert_eq!(number("px"), None);
+    }
+
+    #[test]
+    fn reads_svg_rects() {
+        let layout = parse_svg(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0,-50, 400,300" height="999">
+                <rect x="10" y="20px" width="30" height="40" fill="#ff0000"/>
+                <g id="z=1 depth=0.5">
+                    <g id="details">
+                        <rect x='1' y='2' width='3' height='4' style="stroke:none; fill: rgb(0, 255, 0)"/>
+                    </g>
+                </g>
+                <rect width="5" height="6" data-depth="2"/>
+            </svg>"##,
+        )
+        .unwrap();
+        assert_eq!(layout.height, Some(250.));
+        assert_eq!(layout.rects.len(), 3);
+
+        let rect = &layout.rects[0];
+        assert_eq!((rect.x, rect.y, rect.w, rect.h), (10., 20., 30., 40.));
+        assert_eq!(rect.fill.as_deref(), Some("#ff0000"));
+        assert_eq!(rect.layer, None);
+
+        let rect = &layout.rects[1];
+        assert_eq!(rect.fill.as_deref(), Some("rgb(0, 255, 0)"));
+        assert_eq!(rect.layer.as_deref(), Some("z=1 depth=0.5"));
+
+        let rect = &layout.rects[2];
+        assert_eq!((rect.x, rect.y), (0., 0.));
+        assert_eq!(rect.depth, Some(2.));
+        assert_eq!(rect.layer, None);
+    }
+
+    #[test]
+    fn svg_height_without_view_box() {
+        let layout = parse_svg(r#"<svg width="100" height="80mm"></svg>"#).unwrap();
+        assert_eq!(layout.height, Some(80.));
+        assert!(layout.rects.is_empty());
+    }
+
+    #[test]
+    fn leaves_out_what_is_not_drawn() {
+        let layout = parse_svg(
+            r#"<?xml version="1.0"?>
+            <!-- a <rect> in a comment -->
+            <svg viewBox="0 0 100 100">
+                <defs><clipPath id="page"><rect width="100" height="100"/></clipPath></defs>
+                <mask id="m"><rect width="10" height="10"/></mask>
+                <symbol id="s"><g><rect width="1" height="1"/></g></symbol>
+                <rect x="5" y="5" width="10" height="10" data-note="a > b"/>
+            </svg>"#,
+        )
+        .unwrap();
+        assert_eq!(layout.rects.len(), 1);
+        assert_eq!((layout.rects[0].x, layout.rects[0].w), (5., 10.));
+    }
+
+    #[test]
+    fn applies_translate_and_scale() {
+        let layout = parse_svg(
+            r#"<svg viewBox="0 0 100 100">
+                <g transform="translate(10, 20)">
+                    <g transform="scale(2)">
+                        <rect x="1" y="2" width="3" height="4" transform="translate(1)"/>
+                    </g>
+                    <rect width="3" height="4" transform="matrix(-1 0 0 1 0 0)"/>
+                </g>
+            </svg>"#,
+        )
+        .unwrap();
+        let rect = &layout.rects[0];
+        assert_eq!((rect.x, rect.y, rect.w, rect.h), (14., 24., 6., 8.));
+        let rect = &layout.rects[1];
+        assert_eq!((rect.x, rect.y, rect.w, rect.h), (7., 20., 3., 4.));
+
+        let rotated = r#"<svg><g transform="rotate(45)"><rect width="1" height="1"/></g></svg>"#;
+        assert!(parse_svg(rotated).is_err());
+    }
+
+    #[test]
+    fn reads_fill_colors() {
+        assert_eq!(parse_color("#f00"), Some(Color::rgb_u8(255, 0, 0)));
+        assert_eq!(
+            parse_color("rgb(0, 128, 255)"),
+            Some(Color::rgb_u8(0, 128, 255))
+        );
+        assert_eq!(
+            parse_color("rgb(-4, 127.6, 300)"),
+            Some(Color::rgb_u8(0, 128, 255))
+        );
+        assert_eq!(parse_color("White"), Some(Color::rgb_u8(255, 255, 255)));
+        assert_eq!(parse_color("rgb(0, 128)"), None);
+        assert_eq!(parse_color("none"), None);
+        assert_eq!(parse_color("papayawhip"), None);
+    }
+}
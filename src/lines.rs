@@ -0,0 +1,518 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+    fs,
+    path::Path,
+};
+
+use bevy::prelude::*;
+
+use crate::{
+    export::{capture_name, ExportCamera, ExportSettings},
+    ortho::{collect_cubes, hex, CubeGeometry, Face, Projector},
+    simulation::SimRng,
+    AutoCube,
+};
+// line drawing of the orthographic view with hidden lines removed
+// -- the edges of the faces that look at the camera are the candidates,
+//    edges between two faces turned away are hidden by their own cube
+// -- every other cube hides the parts of an edge that fall inside one of its
+//    front faces on the canvas while that face is nearer than the edge,
+//    faces are flat so along an edge the depth difference is linear and the
+//    hidden part of each face is found exactly
+// -- where two cubes cut into each other their faces meet along a seam that
+//    is no edge of either, the seams are candidates too and go through the
+//    same test against every cube, the two they lie on included, so only
+//    seams on the outside of the pile are drawn
+// -- what is left is chained into polylines per chunk, edges shared by
+//    neighbouring cubes are only drawn once
+// -- svg keeps a group per chunk, dxf a layer per chunk with y up
+
+pub const LINES_DIR: &str = "out/lines";
+
+// hidden parts shorter than this (in canvas units) are not worth a pen lift
+const MIN_PIECE: f32 = 0.01;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LineFormat {
+    Svg,
+    Dxf,
+}
+
+impl LineFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LineFormat::Svg => "SVG",
+            LineFormat::Dxf => "DXF",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            LineFormat::Svg => "svg",
+            LineFormat::Dxf => "dxf",
+        }
+    }
+
+    pub fn all() -> [LineFormat; 2] {
+        [LineFormat::Svg, LineFormat::Dxf]
+    }
+}
+
+#[derive(Resource)]
+pub struct LineExport {
+    pub format: LineFormat,
+    pub stroke_width: f32,
+    pub stroke_color: Color,
+    pub requested: bool,
+}
+
+impl Default for LineExport {
+    fn default() -> Self {
+        LineExport {
+            format: LineFormat::Svg,
+            stroke_width: 1.,
+            stroke_color: Color::BLACK,
+            requested: false,
+        }
+    }
+}
+
+// visible line on the canvas, of the chunk `index`
+pub struct Polyline {
+    pub points: Vec<Vec2>,
+    pub index: usize,
+}
+
+// a front face on the canvas, with the depth of each corner
+struct FlatFace {
+    corners: [Vec2; 4],
+    depths: [f32; 4],
+    // signed area of the first three corners, for the depth plane
+    det: f32,
+}
+
+impl FlatFace {
+    // depth of the face's plane under a canvas point
+    fn depth(&self, point: Vec2) -> f32 {
+        let [p0, p1, p2, _] = self.corners;
+        let (e1, e2, q) = (p1 - p0, p2 - p0, point - p0);
+        let u = q.perp_dot(e2) / self.det;
+        let v = e1.perp_dot(q) / self.det;
+        self.depths[0]
+            + u * (self.depths[1] - self.depths[0])
+            + v * (self.depths[2] - self.depths[0])
+    }
+
+    // part of the segment a + t (b - a) inside the face, t in 0..1
+    fn clip(&self, a: Vec2, b: Vec2) -> Option<(f32, f32)> {
+        let direction = b - a;
+        let sign = self.det.signum();
+        let (mut t0, mut t1) = (0f32, 1f32);
+        for i in 0..4 {
+            let (p, q) = (self.corners[i], self.corners[(i + 1) % 4]);
+            let edge = q - p;
+            // inside while start + t * step >= 0
+            let start = sign * edge.perp_dot(a - p);
+            let step = sign * edge.perp_dot(direction);
+            if step.abs() < f32::EPSILON {
+                if start < 0. {
+                    return None;
+                }
+                continue;
+            }
+            let t = -start / step;
+            match step > 0. {
+                true => t0 = t0.max(t),
+                false => t1 = t1.min(t),
+            }
+        }
+        (t1 > t0).then_some((t0, t1))
+    }
+}
+
+// a cube's front faces on the canvas
+struct Occluder {
+    faces: Vec<FlatFace>,
+    min: Vec2,
+    max: Vec2,
+    // depth differences smaller than this are the same surface
+    tolerance: f32,
+}
+
+// a candidate edge on the canvas
+struct Segment {
+    a: Vec2,
+    b: Vec2,
+    depth_a: f32,
+    depth_b: f32,
+    // the cube whose edge it is, seams have none
+    cube: Option<usize>,
+    index: usize,
+}
+
+fn flatten(projector: &Projector, cube: &CubeGeometry) -> (Occluder, Vec<(Vec3, Vec3)>) {
+    let mut faces = Vec::new();
+    let mut edges: Vec<(Vec3, Vec3)> = Vec::new();
+    for face in cube.faces() {
+        if !projector.faces_camera(face.normal) {
+            continue;
+        }
+        for i in 0..4 {
+            let (a, b) = (face.corners[i], face.corners[(i + 1) % 4]);
+            if !edges.contains(&(a, b)) && !edges.contains(&(b, a)) {
+                edges.push((a, b));
+            }
+        }
+        let projected = face.corners.map(|corner| projector.project(corner));
+        let corners = projected.map(|(point, _)| point);
+        let det = (corners[1] - corners[0]).perp_dot(corners[2] - corners[0]);
+        // faces seen edge on hide nothing
+        if det.abs() < 1e-6 {
+            continue;
+        }
+        faces.push(FlatFace {
+            corners,
+            depths: projected.map(|(_, depth)| depth),
+            det,
+        });
+    }
+    let points = faces.iter().flat_map(|face| face.corners);
+    let min = points.clone().fold(Vec2::splat(f32::MAX), Vec2::min);
+    let max = points.fold(Vec2::splat(f32::MIN), Vec2::max);
+    let tolerance = 1e-3 * (cube.corners[4] - cube.corners[0]).length();
+    (
+        Occluder {
+            faces,
+            min,
+            max,
+            tolerance,
+        },
+        edges,
+    )
+}
+
+// the part of the line p + t d on the face, as a t range
+fn clip_to_face(face: &Face, p: Vec3, d: Vec3, (mut t0, mut t1): (f32, f32)) -> (f32, f32) {
+    let center = face.corners.iter().copied().sum::<Vec3>() / 4.;
+    for i in 0..4 {
+        let (a, b) = (face.corners[i], face.corners[(i + 1) % 4]);
+        let mut inward = face.normal.cross(b - a);
+        if inward.dot(center - a) < 0. {
+            inward = -inward;
+        }
+        // inside while start + t * step >= 0
+        let start = (p - a).dot(inward);
+        let step = d.dot(inward);
+        if step.abs() < f32::EPSILON {
+            if start < 0. {
+                return (1., 0.);
+            }
+            continue;
+        }
+        let t = -start / step;
+        match step > 0. {
+            true => t0 = t0.max(t),
+            false => t1 = t1.min(t),
+        }
+    }
+    (t0, t1)
+}
+
+// where the two faces cross, None for parallel faces and faces that miss
+fn seam(a: &Face, b: &Face) -> Option<(Vec3, Vec3)> {
+    let d = a.normal.cross(b.normal);
+    if d.length_squared() < 1e-6 {
+        return None;
+    }
+    // a point on both planes
+    let (ha, hb) = (a.normal.dot(a.corners[0]), b.normal.dot(b.corners[0]));
+    let c = a.normal.dot(b.normal);
+    let p = ((ha - hb * c) * a.normal + (hb - ha * c) * b.normal) / (1. - c * c);
+
+    let range = clip_to_face(a, p, d, (f32::MIN, f32::MAX));
+    let (t0, t1) = clip_to_face(b, p, d, range);
+    (t1 > t0 && t0 > f32::MIN && t1 < f32::MAX).then(|| (p + d * t0, p + d * t1))
+}
+
+// seams of two cubes, only between faces of which one at least looks at the camera
+fn seams(projector: &Projector, a: &CubeGeometry, b: &CubeGeometry) -> Vec<(Vec3, Vec3)> {
+    let (faces_a, faces_b) = (a.faces(), b.faces());
+    let mut seams = Vec::new();
+    for face_a in &faces_a {
+        for face_b in &faces_b {
+            if !projector.faces_camera(face_a.normal) && !projector.faces_camera(face_b.normal) {
+                continue;
+            }
+            seams.extend(seam(face_a, face_b));
+        }
+    }
+    seams
+}
+
+// box around a cube in world space
+fn bounding_box(cube: &CubeGeometry) -> (Vec3, Vec3) {
+    let min = cube
+        .corners
+        .iter()
+        .copied()
+        .fold(Vec3::splat(f32::MAX), Vec3::min);
+    let max = cube
+        .corners
+        .iter()
+        .copied()
+        .fold(Vec3::splat(f32::MIN), Vec3::max);
+    (min, max)
+}
+
+// parts of the segment hidden by the occluder, as t ranges
+fn hidden(segment: &Segment, occluder: &Occluder, ranges: &mut Vec<(f32, f32)>) {
+    let (a, b) = (segment.a, segment.b);
+    if a.max(b).cmplt(occluder.min).any() || a.min(b).cmpgt(occluder.max).any() {
+        return;
+    }
+    let depth = |t: f32| segment.depth_a + (segment.depth_b - segment.depth_a) * t;
+    for face in &occluder.faces {
+        let Some((t0, t1)) = face.clip(a, b) else {
+            continue;
+        };
+        // how much nearer the face is than the edge, linear in t
+        let nearer = |t: f32| depth(t) - face.depth(a.lerp(b, t)) - occluder.tolerance;
+        let (n0, n1) = (nearer(t0), nearer(t1));
+        match (n0 > 0., n1 > 0.) {
+            (true, true) => ranges.push((t0, t1)),
+            (false, false) => {}
+            // the edge passes through the face where nearer is 0
+            (true, false) => ranges.push((t0, t0 + (t1 - t0) * n0 / (n0 - n1))),
+            (false, true) => ranges.push((t0 + (t1 - t0) * n0 / (n0 - n1), t1)),
+        }
+    }
+}
+
+// visible pieces of every front edge, chained into polylines
+pub fn visible_lines(projector: &Projector, cubes: &[CubeGeometry]) -> Vec<Polyline> {
+    let mut occluders = Vec::new();
+    let mut segments = Vec::new();
+    for (i, cube) in cubes.iter().enumerate() {
+        let (occluder, edges) = flatten(projector, cube);
+        occluders.push(occluder);
+        for (a, b) in edges {
+            let ((a, depth_a), (b, depth_b)) = (projector.project(a), projector.project(b));
+            segments.push(Segment {
+                a,
+                b,
+                depth_a,
+                depth_b,
+                cube: Some(i),
+                index: cube.index,
+            });
+        }
+    }
+    let boxes: Vec<(Vec3, Vec3)> = cubes.iter().map(bounding_box).collect();
+    for i in 0..cubes.len() {
+        for j in i + 1..cubes.len() {
+            let ((min_a, max_a), (min_b, max_b)) = (boxes[i], boxes[j]);
+            if max_a.cmplt(min_b).any() || max_b.cmplt(min_a).any() {
+                continue;
+            }
+            for (a, b) in seams(projector, &cubes[i], &cubes[j]) {
+                let ((a, depth_a), (b, depth_b)) = (projector.project(a), projector.project(b));
+                segments.push(Segment {
+                    a,
+                    b,
+                    depth_a,
+                    depth_b,
+                    cube: None,
+                    index: cubes[i].index,
+                });
+            }
+        }
+    }
+
+    let mut pieces = Vec::new();
+    let mut drawn = HashSet::new();
+    let mut ranges = Vec::new();
+    for segment in &segments {
+        ranges.clear();
+        for (i, occluder) in occluders.iter().enumerate() {
+            if segment.cube != Some(i) {
+                hidden(segment, occluder, &mut ranges);
+            }
+        }
+        ranges.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let length = segment.a.distance(segment.b);
+        let mut visible_from = 0f32;
+        let mut keep = |from: f32, to: f32| {
+            if (to - from) * length < MIN_PIECE {
+                return;
+            }
+            let (a, b) = (
+                segment.a.lerp(segment.b, from),
+                segment.a.lerp(segment.b, to),
+            );
+            // the same edge of two neighbouring cubes
+            let key = match key(a) < key(b) {
+                true => (key(a), key(b)),
+                false => (key(b), key(a)),
+            };
+            if drawn.insert((key, segment.index)) {
+                pieces.push((a, b, segment.index));
+            }
+        };
+        for &(from, to) in &ranges {
+            if from > visible_from {
+                keep(visible_from, from);
+            }
+            visible_from = visible_from.max(to);
+        }
+        if visible_from < 1. {
+            keep(visible_from, 1.);
+        }
+    }
+    chain(pieces)
+}
+
+// canvas points that are the same for chaining, to a hundredth of a unit
+fn key(point: Vec2) -> (i64, i64) {
+    (
+        (point.x * 100.).round() as i64,
+        (point.y * 100.).round() as i64,
+    )
+}
+
+fn chain(pieces: Vec<(Vec2, Vec2, usize)>) -> Vec<Polyline> {
+    let mut ends: HashMap<((i64, i64), usize), Vec<usize>> = HashMap::new();
+    for (i, (a, b, index)) in pieces.iter().enumerate() {
+        ends.entry((key(*a), *index)).or_default().push(i);
+        ends.entry((key(*b), *index)).or_default().push(i);
+    }
+
+    let mut used = vec![false; pieces.len()];
+    // the unused piece ending at `point` and its other end
+    let next = |point: Vec2, index: usize, used: &mut Vec<bool>| {
+        let candidates = ends.get(&(key(point), index))?;
+        let i = candidates.iter().copied().find(|i| !used[*i])?;
+        used[i] = true;
+        let (a, b, _) = pieces[i];
+        Some(if key(a) == key(point) { b } else { a })
+    };
+
+    let mut polylines = Vec::new();
+    for (i, (a, b, index)) in pieces.iter().copied().enumerate() {
+        if used[i] {
+            continue;
+        }
+        used[i] = true;
+        let mut points = vec![a, b];
+        while let Some(point) = next(*points.last().unwrap(), index, &mut used) {
+            points.push(point);
+        }
+        let mut before = Vec::new();
+        while let Some(point) = next(*before.last().unwrap_or(&a), index, &mut used) {
+            before.push(point);
+        }
+        before.reverse();
+        before.extend(points);
+        polylines.push(Polyline {
+            points: before,
+            index,
+        });
+    }
+    polylines
+}
+
+pub fn lines_svg(size: Vec2, polylines: &[Polyline], settings: &LineExport) -> String {
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+        w = size.x,
+        h = size.y
+    );
+    let _ = writeln!(
+        svg,
+        r#"<g fill="none" stroke="{}" stroke-width="{}" stroke-linecap="round" stroke-linejoin="round">"#,
+        hex(settings.stroke_color),
+        settings.stroke_width
+    );
+    let mut chunks: Vec<usize> = polylines.iter().map(|polyline| polyline.index).collect();
+    chunks.sort();
+    chunks.dedup();
+    for chunk in chunks {
+        let _ = writeln!(svg, r#"<g id="chunk_{}">"#, chunk);
+        for polyline in polylines.iter().filter(|polyline| polyline.index == chunk) {
+            let points = polyline
+                .points
+                .iter()
+                .map(|point| format!("{:.2},{:.2}", point.x, point.y))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let _ = writeln!(svg, r#"<polyline points="{}"/>"#, points);
+        }
+        svg.push_str("</g>\n");
+    }
+    svg.push_str("</g>\n</svg>\n");
+    svg
+}
+
+// ascii dxf (R12), canvas units, y up
+pub fn lines_dxf(size: Vec2, polylines: &[Polyline]) -> String {
+    let mut dxf = String::from("0\nSECTION\n2\nENTITIES\n");
+    for polyline in polylines {
+        let layer = format!("chunk_{}", polyline.index);
+        let _ = write!(
+            dxf,
+            "0\nPOLYLINE\n8\n{}\n66\n1\n10\n0.0\n20\n0.0\n30\n0.0\n70\n0\n",
+            layer
+        );
+        for point in &polyline.points {
+            let _ = write!(
+                dxf,
+                "0\nVERTEX\n8\n{}\n10\n{:.3}\n20\n{:.3}\n30\n0.0\n",
+                layer,
+                point.x,
+                size.y - point.y
+            );
+        }
+        let _ = write!(dxf, "0\nSEQEND\n8\n{}\n", layer);
+    }
+    dxf.push_str("0\nENDSEC\n0\nEOF\n");
+    dxf
+}
+
+pub fn export_lines(
+    mut lines: ResMut<LineExport>,
+    export_settings: Res<ExportSettings>,
+    sim_rng: Res<SimRng>,
+    materials: Res<Assets<StandardMaterial>>,
+    camera: Query<(&GlobalTransform, &Projection), With<ExportCamera>>,
+    cubes: Query<(&AutoCube, &GlobalTransform, &Handle<StandardMaterial>)>,
+) {
+    if !lines.requested {
+        return;
+    }
+    lines.requested = false;
+
+    let Ok((transform, projection)) = camera.get_single() else {
+        return;
+    };
+    let size = Vec2::new(export_settings.width as f32, export_settings.height as f32);
+    let Some(projector) = Projector::new(transform, projection, size) else {
+        warn!("line export needs an orthographic camera");
+        return;
+    };
+
+    let polylines = visible_lines(&projector, &collect_cubes(&cubes, &materials));
+    let document = match lines.format {
+        LineFormat::Svg => lines_svg(size, &polylines, &lines),
+        LineFormat::Dxf => lines_dxf(size, &polylines),
+    };
+    let file = Path::new(LINES_DIR)
+        .join(capture_name(sim_rng.seed))
+        .with_extension(lines.format.extension());
+    match fs::create_dir_all(LINES_DIR).and_then(|_| fs::write(&file, document)) {
+        Ok(_) => println!("exported {:?}", file),
+        Err(err) => warn!("could not write {:?}: {}", file, err),
+    }
+}
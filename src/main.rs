@@ -1,45 +1,151 @@
+mod automaton;
+mod batch;
+mod bounds;
+mod export;
+mod geometry;
 mod grid;
+mod growth;
+mod headless;
+mod heightmap;
+mod layout;
+mod lines;
+mod mask;
 mod modes;
+mod ortho;
 mod outline;
+mod passes;
+mod plotter;
+mod record;
+mod region;
+mod scene;
 mod setup;
+mod sheet;
+mod simulation;
 mod spawn_block;
+mod svg;
+mod text;
 mod update;
 mod update_block;
 
+use automaton::{update_automaton, Automaton, GridMasters};
+use batch::{Batch, Variation, BATCHES_DIR};
 use bevy_image_export::ImageExportPlugin;
+use bounds::{drag_bounds, draw_bounds, BoundsGizmo};
+use export::{
+    capture_name, capture_still, resize_export, sync_export_projection, Capture, ExportSettings,
+};
+use geometry::{export_geometry, GeometryExport};
+use growth::Growth;
+use headless::{headless_render, Headless, RenderArgs, USAGE};
+use heightmap::{update_heightmaps, HeightFields, Heightmap};
+use layout::{import_layout, ImportArgs};
+use lines::{export_lines, LineExport};
+use mask::{Mask, Masks};
 use modes::Modes;
-use outline::make_outline_block;
+use outline::{make_outline_block, update_cube_outlines, OutlineStyle};
+use passes::{follow_beauty_export, shade_pass_proxies, spawn_pass_proxies, spawn_passes, Passes};
+use plotter::{export_plotter, PlotterExport};
+use record::{record_sequence, Recording};
+use region::Region;
+use scene::Scene;
 use setup::setup;
+use sheet::{contact_sheet, SheetArgs};
+use simulation::{
+    count_population, reset_chunks, run_steps, tick_chunks, SimRng, Simulation, SimulationStep,
+    StopCondition,
+};
 use spawn_block::init_blocks;
+use svg::{export_svg, SvgExport};
+use text::{text_to_blocks, TextArgs};
 use update::update;
 use update_block::update_block;
 
+use std::{path::Path, sync::atomic::Ordering, time::Duration};
+
 use bevy::{
-    core_pipeline::experimental::taa::TemporalAntiAliasPlugin, prelude::*, window::WindowResolution,
+    app::ScheduleRunnerPlugin,
+    core_pipeline::experimental::taa::TemporalAntiAliasPlugin,
+    prelude::*,
+    window::{ExitCondition, WindowResolution},
+    winit::WinitPlugin,
 };
 use bevy_egui::EguiPlugin;
+use serde::{Deserialize, Serialize};
 
 #[derive(Resource)]
 pub struct UIState {
     mode: Modes,
+    // chunk shown in the chunk panel
+    selected: usize,
 }
 
 #[derive(Resource)]
 pub struct ChunkStates(Vec<ChunkState>);
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ChunkState {
     pub playing: bool,
     pub life_time: i32,
+    // seconds between growth steps, scaled by Simulation.speed
+    pub interval: f32,
     pub base_color: Color,
     pub emissive_color: Color,
     pub scale: f32,
     pub inter_color: ColorChannels,
     pub perceptual_roughness: f32,
     pub bounds: Bounds,
+    pub growth: Growth,
+    // when set the chunk's cubes follow a cellular automaton instead of growing
+    pub automaton: Option<Automaton>,
+    // growth stops spawning above this many live cubes
+    pub max_live: Option<usize>,
+    // the chunk settles once it spawned this many cubes
+    pub max_spawns: Option<usize>,
+    pub stop: StopCondition,
+    // when set every cube of the chunk gets its edges outlined
+    #[serde(default)]
+    pub outline: Option<OutlineStyle>,
+    // when set growth is limited to the inside of an image
+    #[serde(default)]
+    pub mask: Option<Mask>,
+    // when set the chunk is a block field out of an image instead of growing
+    #[serde(default)]
+    pub heightmap: Option<Heightmap>,
+    // when set growth is kept inside this shape instead of the bounds box
+    #[serde(default)]
+    pub region: Option<Region>,
 }
 
-#[derive(Clone)]
+// settings of the default composition's chunk, without bounds
+impl Default for ChunkState {
+    fn default() -> Self {
+        ChunkState {
+            playing: true,
+            life_time: 60,
+            interval: 0.8,
+            scale: SCALE,
+            perceptual_roughness: 0.9,
+            base_color: Color::rgb(1.0, 1.0, 1.0),
+            emissive_color: Color::rgb(0.0, 0.0, 0.0),
+            inter_color: ColorChannels::G,
+            bounds: Bounds {
+                min: Vec3::ZERO,
+                max: Vec3::ZERO,
+            },
+            growth: Growth::default(),
+            automaton: None,
+            max_live: None,
+            max_spawns: None,
+            stop: StopCondition::Never,
+            outline: None,
+            mask: None,
+            heightmap: None,
+            region: None,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Bounds {
     pub min: Vec3,
     pub max: Vec3,
@@ -66,6 +172,10 @@ impl Into<Bounds> for Rect {
 pub struct AutoCube {
     pub index: usize,
     pub life_time: i32,
+    // edge length in world units
+    pub size: f32,
+    // color of its own, from a mask image, instead of the chunk's
+    pub color: Option<Color>,
 }
 
 struct Temp(f32, f32, f32, f32);
@@ -87,6 +197,8 @@ impl Default for AutoCube {
         AutoCube {
             index: 0,
             life_time: LIFETIME,
+            size: 0.0,
+            color: None,
         }
     }
 }
@@ -94,7 +206,7 @@ impl Default for AutoCube {
 const LIFETIME: i32 = 1;
 pub const SCALE: f32 = 230.;
 
-#[derive(PartialEq, Eq, Clone)]
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub enum ColorChannels {
     R,
     G,
@@ -103,9 +215,57 @@ pub enum ColorChannels {
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // contact sheets need no app at all
+    if args.first().is_some_and(|command| command == "sheet") {
+        let sheet =
+            SheetArgs::parse(args.into_iter().skip(1)).and_then(|sheet| contact_sheet(&sheet));
+        match sheet {
+            Ok(out) => println!("wrote {:?}", out),
+            Err(err) => {
+                eprintln!("{}\n{}", err, USAGE);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    // neither do type and layout imports
+    if args.first().is_some_and(|command| command == "text") {
+        let text = TextArgs::parse(args.into_iter().skip(1)).and_then(|text| text_to_blocks(&text));
+        match text {
+            Ok(out) => println!("wrote {:?}", out),
+            Err(err) => {
+                eprintln!("{}\n{}", err, USAGE);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    if args.first().is_some_and(|command| command == "import") {
+        let import =
+            ImportArgs::parse(args.into_iter().skip(1)).and_then(|import| import_layout(&import));
+        match import {
+            Ok(out) => println!("wrote {:?}", out),
+            Err(err) => {
+                eprintln!("{}\n{}", err, USAGE);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let render = match RenderArgs::parse(args.into_iter()) {
+        Ok(render) => render,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+
     // let block_1 = ChunkState {
     //     playing: true,
     //     life_time: LIFETIME,
+    //     interval: 0.8,
     //     scale: SCALE,
     //     perceptual_roughness: 0.9,
     //     base_color: Color::rgb(1.0, 1.0, 1.0),
@@ -119,11 +279,21 @@ fn main() {
     //         z: 0.,
     //     }
     //     .into(),
+    //     growth: Growth::default(),
+    //     automaton: None,
+    //     max_live: None,
+    //     max_spawns: None,
+    //     stop: StopCondition::Never,
+    //     outline: None,
+    //     mask: None,
+    //     heightmap: None,
+    //     region: None,
     // };
 
     let block_2 = ChunkState {
         playing: true,
         life_time: 60,
+        interval: 0.8,
         scale: SCALE,
         perceptual_roughness: 0.9,
         base_color: Color::rgb(1.0, 1.0, 1.0),
@@ -137,10 +307,20 @@ fn main() {
             z: 0.,
         }
         .into(),
+        growth: Growth::default(),
+        automaton: None,
+        max_live: None,
+        max_spawns: None,
+        stop: StopCondition::Never,
+        outline: None,
+        mask: None,
+        heightmap: None,
+        region: None,
     };
     // let block_3 = ChunkState {
     //     playing: true,
     //     life_time: 20,
+    //     interval: 0.8,
     //     scale: SCALE,
     //     perceptual_roughness: 0.9,
     //     base_color: Color::rgb(1.0, 1.0, 1.0),
@@ -154,39 +334,158 @@ fn main() {
     //         z: 0.3,
     //     }
     //     .into(),
+    //     growth: Growth::default(),
+    //     automaton: None,
+    //     max_live: None,
+    //     max_spawns: None,
+    //     stop: StopCondition::Never,
+    //     outline: None,
+    //     mask: None,
+    //     heightmap: None,
+    //     region: None,
     // };
 
-    let chunk_states = ChunkStates(vec![block_2]);
+    let mut chunk_states = ChunkStates(vec![block_2]);
+    let mut export_settings = ExportSettings::default();
+    let mut seed = rand::random();
+
+    // a scene file replaces the composition above
+    let headless = render.map(|mut render| {
+        let mut scene = match Scene::read(&render.scene) {
+            Ok(scene) => scene,
+            Err(err) => {
+                eprintln!("could not read {:?}: {}", render.scene, err);
+                std::process::exit(1);
+            }
+        };
+        scene.seed = render.seed.unwrap_or(scene.seed);
+        chunk_states = ChunkStates(scene.chunks.clone());
+        export_settings = scene.export.clone();
+        seed = scene.seed;
+
+        let jobs = match &render.batch {
+            None => vec![Variation::of(scene)],
+            Some(sweep) => match Batch::read(sweep) {
+                Ok(batch) => batch.variations(&scene),
+                Err(err) => {
+                    eprintln!("could not read {:?}: {}", sweep, err);
+                    std::process::exit(1);
+                }
+            },
+        };
+        if render.batch.is_some() && render.out.is_none() {
+            render.out = Some(Path::new(BATCHES_DIR).join(capture_name(seed)));
+        }
+        Headless::new(render, jobs)
+    });
+    let preview = export_settings.preview_size();
 
     let export_plugin = ImageExportPlugin::default();
     let export_threads = export_plugin.threads.clone();
+    let failures = headless.as_ref().map(Headless::failures);
 
-    App::new()
-        .insert_resource(AmbientLight {
-            brightness: 3.0,
-            ..default()
-        })
-        .insert_resource(UIState { mode: Modes::Home })
-        .insert_resource(chunk_states)
-        .insert_resource(ClearColor(Color::rgb(0.0, 0.0, 0.0)))
-        .add_plugins((
-            DefaultPlugins.set(WindowPlugin {
+    let mut app = App::new();
+    match headless {
+        Some(headless) => {
+            app.add_plugins((
+                DefaultPlugins
+                    .set(WindowPlugin {
+                        primary_window: None,
+                        exit_condition: ExitCondition::DontExit,
+                        close_when_requested: false,
+                    })
+                    .disable::<WinitPlugin>(),
+                ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(1.0 / 60.0)),
+            ))
+            .insert_resource(headless)
+            .add_systems(Update, headless_render.before(reset_chunks));
+        }
+        None => {
+            app.add_plugins(DefaultPlugins.set(WindowPlugin {
                 primary_window: Some(Window {
-                    resolution: WindowResolution::new(768.0, 768.0).with_scale_factor_override(1.0),
+                    resolution:
+                        WindowResolution::new(preview.x, preview.y).with_scale_factor_override(1.0),
                     ..default()
                 }),
                 ..default()
-            }),
-            export_plugin,
-        ))
-        .add_plugins(TemporalAntiAliasPlugin)
-        .add_plugins(EguiPlugin)
-        .add_systems(Startup, setup)
-        .add_systems(Startup, init_blocks)
-        .add_systems(FixedUpdate, update_block)
-        .add_systems(Update, update)
-        .insert_resource(FixedTime::new_from_secs(0.8))
-        .run();
+            }))
+            .add_plugins(EguiPlugin)
+            .init_resource::<BoundsGizmo>()
+            .add_systems(Update, (update, drag_bounds, draw_bounds).chain());
+        }
+    }
+
+    app.insert_resource(AmbientLight {
+        brightness: 3.0,
+        ..default()
+    })
+    .insert_resource(UIState {
+        mode: Modes::Home,
+        selected: 0,
+    })
+    .insert_resource(chunk_states)
+    .insert_resource(export_settings)
+    .init_resource::<Capture>()
+    .init_resource::<Recording>()
+    .init_resource::<SvgExport>()
+    .init_resource::<LineExport>()
+    .init_resource::<PlotterExport>()
+    .init_resource::<GeometryExport>()
+    .init_resource::<Passes>()
+    .init_resource::<GridMasters>()
+    .init_resource::<Masks>()
+    .init_resource::<HeightFields>()
+    .init_resource::<Simulation>()
+    .insert_resource(SimRng::new(seed))
+    .insert_resource(ClearColor(Color::rgb(0.0, 0.0, 0.0)))
+    .add_plugins(export_plugin)
+    .add_plugins(TemporalAntiAliasPlugin)
+    .add_systems(Startup, setup)
+    .add_systems(Startup, init_blocks)
+    .add_systems(
+        Update,
+        (
+            reset_chunks,
+            // the despawns and seed blocks of a reset land before anything counts or grows
+            apply_deferred,
+            count_population,
+            tick_chunks,
+            run_steps,
+            update_heightmaps,
+        )
+            .chain(),
+    )
+    .add_systems(SimulationStep, (update_block, update_automaton).chain())
+    .add_systems(Update, record_sequence.before(tick_chunks))
+    .add_systems(
+        Update,
+        (
+            resize_export,
+            sync_export_projection,
+            capture_still,
+            export_svg,
+            export_lines,
+            export_plotter,
+            export_geometry,
+            update_cube_outlines,
+        ),
+    )
+    .add_systems(
+        Update,
+        (
+            spawn_passes,
+            spawn_pass_proxies,
+            shade_pass_proxies.after(follow_beauty_export),
+            follow_beauty_export
+                .after(capture_still)
+                .after(record_sequence)
+                .after(update),
+        ),
+    )
+    .run();
 
     export_threads.finish();
+    if failures.is_some_and(|failures| failures.load(Ordering::Relaxed) > 0) {
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,177 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    region::{random_in_box, SAMPLES},
+    ChunkState,
+};
+// growth regions from an image, a logo or a letterform in the assets folder
+// -- the image is stretched over the xy rectangle of the chunk's bounds, its
+//    top at max y, and runs through the whole depth of the bounds
+// -- a point is inside when the pixel under it covers more than the
+//    threshold, coverage is the alpha, or the darkness for images without
+//    alpha so black on white artwork works as well
+// -- children that land outside are drawn again a few times and dropped
+//    when they never get in, seed blocks start on a random inside point
+// -- images are loaded the first time a chunk needs them and kept, a name
+//    that can't be read is tried again every RETRY so a file added later or
+//    a path still being typed gets picked up, its warning is logged once,
+//    until then the chunk doesn't grow
+
+pub const MASKS_DIR: &str = "assets";
+
+// wait before reading an image that failed again
+const RETRY: Duration = Duration::from_secs(1);
+
+// times the growth rule is asked again for children inside the mask
+pub const ATTEMPTS: usize = 8;
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Mask {
+    // image file in the assets folder
+    pub image: String,
+    // coverage from 0 to 1 a pixel needs to be inside
+    pub threshold: f32,
+    // cubes take the color of the pixel under their centre
+    pub color: bool,
+}
+
+impl Default for Mask {
+    fn default() -> Self {
+        Mask {
+            image: String::new(),
+            threshold: 0.5,
+            color: false,
+        }
+    }
+}
+
+pub struct MaskImage {
+    width: u32,
+    height: u32,
+    coverage: Vec<f32>,
+    colors: Vec<Color>,
+}
+
+impl MaskImage {
+    pub fn load(path: impl AsRef<Path>) -> Result<MaskImage, String> {
+        let path = path.as_ref();
+        let image =
+            image::open(path).map_err(|err| format!("could not read {:?}: {}", path, err))?;
+        let alpha = image.color().has_alpha();
+        let image = image.to_rgba8();
+        let (width, height) = image.dimensions();
+        let mut coverage = Vec::with_capacity((width * height) as usize);
+        let mut colors = Vec::with_capacity((width * height) as usize);
+        for pixel in image.pixels() {
+            let [r, g, b, a] = pixel.0;
+            coverage.push(match alpha {
+                true => a as f32 / 255.,
+                false => 1. - (r as f32 * 0.299 + g as f32 * 0.587 + b as f32 * 0.114) / 255.,
+            });
+            colors.push(Color::rgba_u8(r, g, b, 255));
+        }
+        Ok(MaskImage {
+            width,
+            height,
+            coverage,
+            colors,
+        })
+    }
+
+    // pixel under a world point, None outside the bounds
+    fn pixel(&self, chunk: &ChunkState, point: Vec3) -> Option<usize> {
+        let min = chunk.bounds.min.min(chunk.bounds.max) * chunk.scale;
+        let max = chunk.bounds.min.max(chunk.bounds.max) * chunk.scale;
+        let size = max - min;
+        if size.x <= 0. || size.y <= 0. || self.width == 0 || self.height == 0 {
+            return None;
+        }
+        let u = (point.x - min.x) / size.x;
+        let v = (max.y - point.y) / size.y;
+        if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) {
+            return None;
+        }
+        let x = ((u * self.width as f32) as u32).min(self.width - 1);
+        let y = ((v * self.height as f32) as u32).min(self.height - 1);
+        Some((y * self.width + x) as usize)
+    }
+
+    pub fn contains(&self, chunk: &ChunkState, mask: &Mask, point: Vec3) -> bool {
+        self.pixel(chunk, point)
+            .is_some_and(|pixel| self.coverage[pixel] > mask.threshold)
+    }
+
+    pub fn color(&self, chunk: &ChunkState, point: Vec3) -> Option<Color> {
+        self.pixel(chunk, point).map(|pixel| self.colors[pixel])
+    }
+
+    // a random point inside the mask and the chunk's region, None when none
+    // turns up
+    pub fn sample(&self, chunk: &ChunkState, mask: &Mask, rng: &mut dyn RngCore) -> Option<Vec3> {
+        let region = chunk.region();
+        let (min, max) = region.aabb();
+        (0..SAMPLES)
+            .map(|_| random_in_box(min, max, rng))
+            .filter(|point| region.contains(*point))
+            .map(|point| point * chunk.scale)
+            .find(|point| self.contains(chunk, mask, *point))
+    }
+}
+
+enum Loaded {
+    Image(MaskImage),
+    // when it was last tried
+    Failed(Instant),
+}
+
+#[derive(Resource, Default)]
+pub struct Masks(HashMap<String, Loaded>);
+
+impl Masks {
+    // the mask's image, loaded on first use, None when it can't be read
+    pub fn get(&mut self, mask: &Mask) -> Option<&MaskImage> {
+        self.image(&mask.image)
+    }
+
+    // any image of the assets folder, heightmaps read theirs through here too
+    pub fn image(&mut self, image: &str) -> Option<&MaskImage> {
+        if image.is_empty() {
+            return None;
+        }
+        let retry = match self.0.get(image) {
+            None => true,
+            Some(Loaded::Failed(tried)) => tried.elapsed() >= RETRY,
+            Some(Loaded::Image(_)) => false,
+        };
+        if retry {
+            let loaded = match MaskImage::load(Path::new(MASKS_DIR).join(image)) {
+                Ok(loaded) => Loaded::Image(loaded),
+                Err(err) => {
+                    if !self.0.contains_key(image) {
+                        warn!("{}", err);
+                    }
+                    Loaded::Failed(Instant::now())
+                }
+            };
+            self.0.insert(image.to_string(), loaded);
+        }
+        match self.0.get(image) {
+            Some(Loaded::Image(loaded)) => Some(loaded),
+            _ => None,
+        }
+    }
+}
+
+// the cube color for a point, the pixel's when the mask asks for it
+pub fn cube_color(image: Option<&MaskImage>, chunk: &ChunkState, point: Vec3) -> Option<Color> {
+    chunk.mask.as_ref().filter(|mask| mask.color)?;
+    image?.color(chunk, point)
+}
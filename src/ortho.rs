@@ -0,0 +1,131 @@
+use bevy::{prelude::*, render::camera::CameraProjection};
+
+use crate::AutoCube;
+
+// the orthographic projection of the export camera, done on the cpu
+// -- used by the vector exports so they frame exactly what the raster export frames
+// -- points come out in canvas units, (0, 0) top left, y down like svg
+
+pub struct Projector {
+    view: Mat4,
+    forward: Vec3,
+    area: Rect,
+    pub size: Vec2,
+}
+
+impl Projector {
+    // None for perspective cameras
+    pub fn new(transform: &GlobalTransform, projection: &Projection, size: Vec2) -> Option<Self> {
+        let Projection::Orthographic(orthographic) = projection else {
+            return None;
+        };
+        // the area is only filled in once the camera has rendered, update a copy
+        // so it matches the canvas aspect ratio either way
+        let mut orthographic = orthographic.clone();
+        orthographic.update(size.x, size.y);
+        Some(Projector {
+            view: transform.compute_matrix().inverse(),
+            forward: transform.forward(),
+            area: orthographic.area,
+            size,
+        })
+    }
+
+    // canvas position and depth (bigger is further away)
+    pub fn project(&self, point: Vec3) -> (Vec2, f32) {
+        let view = self.view.transform_point3(point);
+        let x = (view.x - self.area.min.x) / self.area.width() * self.size.x;
+        let y = (self.area.max.y - view.y) / self.area.height() * self.size.y;
+        (Vec2::new(x, y), -view.z)
+    }
+
+    pub fn faces_camera(&self, normal: Vec3) -> bool {
+        normal.dot(self.forward) < 0.0
+    }
+}
+
+// a cube of the scene in world space
+#[derive(Clone)]
+pub struct CubeGeometry {
+    pub corners: [Vec3; 8],
+    pub color: Color,
+    // chunk the cube belongs to
+    pub index: usize,
+}
+
+// corners of each face in order around it, and its normal
+const FACES: [([usize; 4], Vec3); 6] = [
+    ([0, 2, 3, 1], Vec3::NEG_X),
+    ([4, 5, 7, 6], Vec3::X),
+    ([0, 1, 5, 4], Vec3::NEG_Y),
+    ([2, 6, 7, 3], Vec3::Y),
+    ([0, 4, 6, 2], Vec3::NEG_Z),
+    ([1, 3, 7, 5], Vec3::Z),
+];
+
+pub struct Face {
+    pub corners: [Vec3; 4],
+    pub normal: Vec3,
+}
+
+impl CubeGeometry {
+    // corner i has x from bit 2, y from bit 1, z from bit 0
+    pub fn new(transform: &GlobalTransform, size: f32, color: Color, index: usize) -> Self {
+        let half = size / 2.;
+        let mut corners = [Vec3::ZERO; 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let local = Vec3::new(
+                if i & 4 == 0 { -half } else { half },
+                if i & 2 == 0 { -half } else { half },
+                if i & 1 == 0 { -half } else { half },
+            );
+            *corner = transform.transform_point(local);
+        }
+        CubeGeometry {
+            corners,
+            color,
+            index,
+        }
+    }
+
+    pub fn faces(&self) -> Vec<Face> {
+        let rotation = self.rotation();
+        FACES
+            .iter()
+            .map(|(corners, normal)| Face {
+                corners: corners.map(|i| self.corners[i]),
+                normal: rotation * *normal,
+            })
+            .collect()
+    }
+
+    // orientation of the cube recovered from its corners
+    fn rotation(&self) -> Quat {
+        let x = (self.corners[4] - self.corners[0]).normalize_or_zero();
+        let y = (self.corners[2] - self.corners[0]).normalize_or_zero();
+        let z = (self.corners[1] - self.corners[0]).normalize_or_zero();
+        Quat::from_mat3(&Mat3::from_cols(x, y, z))
+    }
+}
+
+// cubes of the scene, live AutoCubes with the color of their material
+pub fn collect_cubes(
+    cubes: &Query<(&AutoCube, &GlobalTransform, &Handle<StandardMaterial>)>,
+    materials: &Assets<StandardMaterial>,
+) -> Vec<CubeGeometry> {
+    cubes
+        .iter()
+        .map(|(cube, transform, material)| {
+            let color = materials
+                .get(material)
+                .map_or(Color::WHITE, |material| material.base_color);
+            CubeGeometry::new(transform, cube.size, color, cube.index)
+        })
+        .collect()
+}
+
+pub fn hex(color: Color) -> String {
+    let [r, g, b, _] = color.as_rgba_f32();
+    let channel = |c: f32| (c.clamp(0.0, 1.0) * 255.).round() as u8;
+    format!("#{:02x}{:02x}{:02x}", channel(r), channel(g), channel(b))
+}
@@ -1,192 +1,249 @@
-use bevy::prelude::*;
-
-use crate::Bounds;
-// make a block that will take Bounds as input and output a
-// series of meshes that create outline of a cuboid
-
-pub fn make_outline_block(bounds: &Bounds) -> Vec<Mesh> {
-    const OUTLINE_WIDTH: f32 = 0.01;
-
-    // front top
-    let c1 = (
-        Vec3::new(
-            bounds.min.x,
-            bounds.min.y - OUTLINE_WIDTH,
-            bounds.max.z + OUTLINE_WIDTH,
-        ) * 3.,
-        Vec3::new(
-            bounds.max.x,
-            bounds.min.y + OUTLINE_WIDTH,
-            bounds.max.z - OUTLINE_WIDTH,
-        ) * 3.,
-    );
-
-    // back top
-    let c2 = (
-        Vec3::new(
-            bounds.min.x,
-            bounds.min.y - OUTLINE_WIDTH,
-            bounds.min.z + OUTLINE_WIDTH,
-        ) * 3.,
-        Vec3::new(
-            bounds.max.x,
-            bounds.min.y + OUTLINE_WIDTH,
-            bounds.min.z - OUTLINE_WIDTH,
-        ) * 3.,
-    );
-
-    // front bottom
-    let c3 = (
-        Vec3::new(
-            bounds.min.x,
-            bounds.max.y - OUTLINE_WIDTH,
-            bounds.max.z + OUTLINE_WIDTH,
-        ) * 3.,
-        Vec3::new(
-            bounds.max.x,
-            bounds.max.y + OUTLINE_WIDTH,
-            bounds.max.z - OUTLINE_WIDTH,
-        ) * 3.,
-    );
-
-    // back bottom
-    let c4 = (
-        Vec3::new(
-            bounds.min.x,
-            bounds.max.y - OUTLINE_WIDTH,
-            bounds.min.z + OUTLINE_WIDTH,
-        ) * 3.,
-        Vec3::new(
-            bounds.max.x,
-            bounds.max.y + OUTLINE_WIDTH,
-            bounds.min.z - OUTLINE_WIDTH,
-        ) * 3.,
-    );
-
-    // left top
-    let c5 = (
-        Vec3::new(
-            bounds.min.x - OUTLINE_WIDTH,
-            bounds.max.y + OUTLINE_WIDTH,
-            bounds.min.z,
-        ) * 3.,
-        Vec3::new(
-            bounds.min.x + OUTLINE_WIDTH,
-            bounds.max.y - OUTLINE_WIDTH,
-            bounds.max.z,
-        ) * 3.,
-    );
-    // left bottom
-    let c6 = (
-        Vec3::new(
-            bounds.min.x - OUTLINE_WIDTH,
-            bounds.min.y + OUTLINE_WIDTH,
-            bounds.min.z,
-        ) * 3.,
-        Vec3::new(
-            bounds.min.x + OUTLINE_WIDTH,
-            bounds.min.y - OUTLINE_WIDTH,
-            bounds.max.z,
-        ) * 3.,
-    );
-    // right top
-    let c7 = (
-        Vec3::new(
-            bounds.max.x - OUTLINE_WIDTH,
-            bounds.max.y + OUTLINE_WIDTH,
-            bounds.min.z,
-        ) * 3.,
-        Vec3::new(
-            bounds.max.x + OUTLINE_WIDTH,
-            bounds.max.y - OUTLINE_WIDTH,
-            bounds.max.z,
-        ) * 3.,
-    );
-
-    // right bottom
-    let c8 = (
-        Vec3::new(
-            bounds.max.x - OUTLINE_WIDTH,
-            bounds.min.y + OUTLINE_WIDTH,
-            bounds.min.z,
-        ) * 3.,
-        Vec3::new(
-            bounds.max.x + OUTLINE_WIDTH,
-            bounds.min.y - OUTLINE_WIDTH,
-            bounds.max.z,
-        ) * 3.,
-    );
-
-    // left front edge
-    let c9 = (
-        Vec3::new(
-            bounds.min.x - OUTLINE_WIDTH,
-            bounds.min.y,
-            bounds.max.z + OUTLINE_WIDTH,
-        ) * 3.,
-        Vec3::new(
-            bounds.min.x + OUTLINE_WIDTH,
-            bounds.max.y,
-            bounds.max.z - OUTLINE_WIDTH,
-        ) * 3.,
-    );
-
-    // left back edge
-    let c10 = (
-        Vec3::new(
-            bounds.min.x - OUTLINE_WIDTH,
-            bounds.min.y,
-            bounds.min.z + OUTLINE_WIDTH,
-        ) * 3.,
-        Vec3::new(
-            bounds.min.x + OUTLINE_WIDTH,
-            bounds.max.y,
-            bounds.min.z - OUTLINE_WIDTH,
-        ) * 3.,
-    );
-
-    // right front edge
-    let c11 = (
-        Vec3::new(
-            bounds.max.x - OUTLINE_WIDTH,
-            bounds.min.y,
-            bounds.max.z + OUTLINE_WIDTH,
-        ) * 3.,
-        Vec3::new(
-            bounds.max.x + OUTLINE_WIDTH,
-            bounds.max.y,
-            bounds.max.z - OUTLINE_WIDTH,
-        ) * 3.,
-    );
-
-    // right back edge
-    let c12 = (
-        Vec3::new(
-            bounds.max.x - OUTLINE_WIDTH,
-            bounds.min.y,
-            bounds.min.z + OUTLINE_WIDTH,
-        ) * 3.,
-        Vec3::new(
-            bounds.max.x + OUTLINE_WIDTH,
-            bounds.max.y,
-            bounds.min.z - OUTLINE_WIDTH,
-        ) * 3.,
-    );
-
-    let mut v = Vec::new();
-
-    v.push(Mesh::from(shape::Box::from_corners(c1.0, c1.1)));
-    v.push(Mesh::from(shape::Box::from_corners(c2.0, c2.1)));
-    v.push(Mesh::from(shape::Box::from_corners(c3.0, c3.1)));
-    v.push(Mesh::from(shape::Box::from_corners(c4.0, c4.1)));
-    v.push(Mesh::from(shape::Box::from_corners(c5.0, c5.1)));
-    v.push(Mesh::from(shape::Box::from_corners(c6.0, c6.1)));
-    v.push(Mesh::from(shape::Box::from_corners(c7.0, c7.1)));
-    v.push(Mesh::from(shape::Box::from_corners(c8.0, c8.1)));
-    v.push(Mesh::from(shape::Box::from_corners(c9.0, c9.1)));
-    v.push(Mesh::from(shape::Box::from_corners(c10.0, c10.1)));
-    v.push(Mesh::from(shape::Box::from_corners(c11.0, c11.1)));
-    v.push(Mesh::from(shape::Box::from_corners(c12.0, c12.1)));
-
-    v
+use std::collections::HashMap;
+
+use bevy::{prelude::*, render::mesh::PrimitiveTopology};
+use serde::{Deserialize, Serialize};
+
+use crate::{AutoCube, Bounds, ChunkStates};
+// the outline of a Bounds as one mesh
+// -- every edge of the cuboid is a beam of square section `width` wide,
+//    centred on the edge, in world units (bounds times the chunk scale)
+// -- overlapping joins run every beam half a width past the corner so the
+//    three beams of a corner overlap in a little cube
+// -- mitred joins cut the beam ends on the corner's diagonals so the beams
+//    meet without overlapping, a beam owns the part of the corner that lies
+//    furthest along its own direction
+// -- the stroke color goes into the vertex colors
+// -- chunks with an outline style get one around every cube, as a child of
+//    the cube so it moves and goes with it
+// -- cube outlines are built white and share one material per chunk that
+//    carries the stroke color, so a new color only changes that material
+//    and a new width or join rebuilds the meshes
+
+// marks outline meshes so exports can find them
+#[derive(Component)]
+pub struct Outline;
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Join {
+    Overlap,
+    Mitre,
+}
+
+impl Join {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Join::Overlap => "Overlap",
+            Join::Mitre => "Mitre",
+        }
+    }
+
+    pub fn all() -> [Join; 2] {
+        [Join::Overlap, Join::Mitre]
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct OutlineStyle {
+    pub width: f32,
+    pub join: Join,
+    pub color: Color,
+}
+
+impl Default for OutlineStyle {
+    fn default() -> Self {
+        OutlineStyle {
+            width: 2.,
+            join: Join::Mitre,
+            color: Color::BLACK,
+        }
+    }
+}
+
+// outline of a cube, with the width and join it was built with
+#[derive(Component)]
+pub struct CubeOutline(f32, Join);
+
+// an edge of the cuboid, from `start` along `axis` for `length`, `inward_b`
+// and `inward_c` point from the edge into the cuboid along the other two axes
+struct Edge {
+    start: Vec3,
+    axis: Vec3,
+    length: f32,
+    inward_b: Vec3,
+    inward_c: Vec3,
+}
+
+fn edges(min: Vec3, max: Vec3) -> Vec<Edge> {
+    let mut edges = Vec::new();
+    // axis, then the two other axes
+    for (a, b, c) in [(0, 1, 2), (1, 2, 0), (2, 0, 1)] {
+        for on_max_b in [false, true] {
+            for on_max_c in [false, true] {
+                let mut start = min;
+                start[b] = if on_max_b { max[b] } else { min[b] };
+                start[c] = if on_max_c { max[c] } else { min[c] };
+                let inward = |axis: usize, on_max: bool| {
+                    let mut inward = Vec3::ZERO;
+                    inward[axis] = if on_max { -1. } else { 1. };
+                    inward
+                };
+                let mut axis = Vec3::ZERO;
+                axis[a] = 1.;
+                edges.push(Edge {
+                    start,
+                    axis,
+                    length: max[a] - min[a],
+                    inward_b: inward(b, on_max_b),
+                    inward_c: inward(c, on_max_c),
+                });
+            }
+        }
+    }
+    edges
+}
+
+pub fn make_outline_block(
+    bounds: &Bounds,
+    scale: f32,
+    width: f32,
+    join: Join,
+    color: Color,
+) -> Mesh {
+    let min = bounds.min.min(bounds.max) * scale;
+    let max = bounds.min.max(bounds.max) * scale;
+    let half = width / 2.;
+
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+    for edge in edges(min, max) {
+        // 8 corners of the beam, bit 2 picks the end, bit 1 the side along
+        // inward_b and bit 0 the side along inward_c
+        let mut corners = [Vec3::ZERO; 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let u = if i & 2 == 0 { -half } else { half };
+            let v = if i & 1 == 0 { -half } else { half };
+            let past_corner = match join {
+                Join::Overlap => half,
+                Join::Mitre => -u.max(v),
+            };
+            let s = match i & 4 == 0 {
+                true => -past_corner,
+                false => edge.length + past_corner,
+            };
+            *corner = edge.start + edge.axis * s + edge.inward_b * u + edge.inward_c * v;
+        }
+
+        // the end caps are folded along the (-,-) (+,+) diagonal for mitres
+        let quads = [
+            [0, 2, 3, 1],
+            [4, 6, 7, 5],
+            [0, 4, 5, 1],
+            [2, 6, 7, 3],
+            [0, 4, 6, 2],
+            [1, 5, 7, 3],
+        ];
+        let center = edge.start + edge.axis * edge.length / 2.;
+        for [a, b, c, d] in quads {
+            for [a, b, c] in [[a, b, c], [a, c, d]] {
+                let [a, b, c] = [corners[a], corners[b], corners[c]];
+                let mut normal = (b - a).cross(c - a).normalize_or_zero();
+                // wind every triangle to face away from the beam
+                let triangle = if normal.dot((a + b + c) / 3. - center) < 0. {
+                    normal = -normal;
+                    [a, c, b]
+                } else {
+                    [a, b, c]
+                };
+                positions.extend(triangle.map(|p| p.to_array()));
+                normals.extend([normal.to_array(); 3]);
+            }
+        }
+    }
+
+    let colors = vec![color.as_linear_rgba_f32(); positions.len()];
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    mesh
+}
+
+// adds, rebuilds and removes cube outlines to follow the chunks' styles
+pub fn update_cube_outlines(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut chunk_materials: Local<HashMap<usize, Handle<StandardMaterial>>>,
+    chunk_states: Res<ChunkStates>,
+    cubes: Query<(Entity, &AutoCube, Option<&Children>)>,
+    outlines: Query<&CubeOutline>,
+) {
+    for (index, chunk) in chunk_states.0.iter().enumerate() {
+        let Some(style) = &chunk.outline else {
+            continue;
+        };
+        let material = chunk_materials.entry(index).or_insert_with(|| {
+            // flat strokes for the technical drawing look
+            materials.add(StandardMaterial {
+                base_color: style.color,
+                unlit: true,
+                ..default()
+            })
+        });
+        if materials
+            .get(material)
+            .is_some_and(|m| m.base_color != style.color)
+        {
+            if let Some(m) = materials.get_mut(material) {
+                m.base_color = style.color;
+            }
+        }
+    }
+
+    for (entity, cube, children) in cubes.iter() {
+        let style = chunk_states
+            .0
+            .get(cube.index)
+            .and_then(|chunk| chunk.outline.as_ref());
+
+        let mut current = false;
+        if let Some(children) = children {
+            for child in children.iter() {
+                let Ok(CubeOutline(width, join)) = outlines.get(*child) else {
+                    continue;
+                };
+                let same = style.is_some_and(|style| style.width == *width && style.join == *join);
+                if !current && same {
+                    current = true;
+                } else {
+                    commands.entity(*child).despawn_recursive();
+                }
+            }
+        }
+        let Some(style) = style.filter(|_| !current) else {
+            continue;
+        };
+        let Some(material) = chunk_materials.get(&cube.index) else {
+            continue;
+        };
+
+        let half = Vec3::splat(cube.size / 2.);
+        let bounds = Bounds {
+            min: -half,
+            max: half,
+        };
+        let mesh = make_outline_block(&bounds, 1., style.width, style.join, Color::WHITE);
+        let outline = commands
+            .spawn((
+                PbrBundle {
+                    mesh: meshes.add(mesh),
+                    material: material.clone(),
+                    ..default()
+                },
+                Outline,
+                CubeOutline(style.width, style.join),
+            ))
+            .id();
+        commands.entity(entity).add_child(outline);
+    }
 }
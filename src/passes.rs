@@ -0,0 +1,328 @@
+use std::path::{Path, PathBuf};
+
+use bevy::{
+    core_pipeline::{
+        clear_color::ClearColorConfig,
+        experimental::taa::TemporalAntiAliasBundle,
+        tonemapping::{DebandDither, Tonemapping},
+    },
+    pbr::{ScreenSpaceAmbientOcclusionBundle, ScreenSpaceAmbientOcclusionSettings},
+    prelude::*,
+    render::{
+        camera::RenderTarget, mesh::VertexAttributeValues, render_resource::TextureFormat,
+        view::RenderLayers,
+    },
+};
+use bevy_image_export::{ImageExportBundle, ImageExportSettings, ImageExportSource};
+
+use crate::{
+    export::{export_texture, ExportCamera, ExportSettings},
+    setup::PlisCamera,
+    AutoCube,
+};
+// extra passes rendered next to the beauty pass, for compositing
+// -- every pass has its own camera, texture and exporter, the cameras sit
+//    next to the export camera and share its projection
+// -- hdr is the scene again, without tonemapping, into a float texture
+// -- depth, normals and ids are drawn from unlit stand-ins of the cubes on
+//    a render layer of their own, depth and normals through vertex colors
+//    (exact for an orthographic camera, view depth is linear on screen)
+// -- the pass exporters export whenever the beauty exporter does, each into
+//    a folder named after the pass inside its output dir, stills and
+//    sequences then name the frames after the beauty frame:
+//    00000.png, 00000.depth.exr, 00000.normal.png ..
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Pass {
+    // linear color, exr
+    Hdr,
+    // distance to the camera plane in world units, exr
+    Depth,
+    // view space normals as n * 0.5 + 0.5
+    Normal,
+    // a flat color per chunk, see id_color
+    Id,
+}
+
+impl Pass {
+    pub fn all() -> [Pass; 4] {
+        [Pass::Hdr, Pass::Depth, Pass::Normal, Pass::Id]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pass::Hdr => "hdr",
+            Pass::Depth => "depth",
+            Pass::Normal => "normal",
+            Pass::Id => "id",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Pass::Hdr | Pass::Depth => "exr",
+            Pass::Normal | Pass::Id => "png",
+        }
+    }
+
+    fn format(&self) -> TextureFormat {
+        match self {
+            Pass::Hdr | Pass::Depth => TextureFormat::Rgba32Float,
+            Pass::Normal | Pass::Id => TextureFormat::Rgba8Unorm,
+        }
+    }
+
+    // None renders the scene itself
+    fn layer(&self) -> Option<u8> {
+        match self {
+            Pass::Hdr => None,
+            Pass::Depth => Some(1),
+            Pass::Normal => Some(2),
+            Pass::Id => Some(3),
+        }
+    }
+
+    // where the pass exporter writes, given where the beauty exporter writes
+    pub fn dir(&self, output_dir: impl AsRef<Path>) -> PathBuf {
+        output_dir.as_ref().join(self.name())
+    }
+
+    // <frame>.<pass>.<ext> next to the beauty frame <frame>.<ext>
+    pub fn file(&self, frame: impl AsRef<Path>) -> PathBuf {
+        let frame = frame.as_ref();
+        let stem = frame.file_stem().unwrap_or_default().to_string_lossy();
+        frame.with_file_name(format!("{}.{}.{}", stem, self.name(), self.extension()))
+    }
+}
+
+// golden angle hues so neighbouring chunks are far apart
+pub fn id_color(index: usize) -> Color {
+    Color::hsl((index as f32 * 137.508) % 360., 1., 0.5)
+}
+
+#[derive(Resource, Default)]
+pub struct Passes {
+    pub enabled: Vec<Pass>,
+}
+
+#[derive(Component)]
+pub struct PassCamera;
+
+#[derive(Component)]
+pub struct PassExport(pub Pass);
+
+// stand-in of a cube for one of the layered passes, a child of the cube
+#[derive(Component)]
+pub struct PassProxy(pub Pass);
+
+// (re)makes the pass cameras and exporters when the passes or the export size change
+pub fn spawn_passes(
+    mut commands: Commands,
+    passes: Res<Passes>,
+    export_settings: Res<ExportSettings>,
+    mut images: ResMut<Assets<Image>>,
+    mut export_sources: ResMut<Assets<ImageExportSource>>,
+    preview: Query<Entity, With<PlisCamera>>,
+    spawned: Query<Entity, Or<(With<PassCamera>, With<PassExport>)>>,
+) {
+    if !passes.is_changed() && !export_settings.is_changed() {
+        return;
+    }
+    let Ok(preview) = preview.get_single() else {
+        return;
+    };
+    for entity in spawned.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    for (order, pass) in passes.enabled.iter().enumerate() {
+        let texture = export_texture(&mut images, &export_settings, pass.format());
+        let camera = Camera {
+            hdr: true,
+            order: -2 - order as isize,
+            target: RenderTarget::Image(texture.clone()),
+            ..default()
+        };
+        let mut camera = match pass.layer() {
+            None => commands.spawn((
+                Camera3dBundle {
+                    camera,
+                    tonemapping: Tonemapping::None,
+                    dither: DebandDither::Disabled,
+                    ..default()
+                },
+                ScreenSpaceAmbientOcclusionBundle {
+                    settings: ScreenSpaceAmbientOcclusionSettings {
+                        quality_level: bevy::pbr::ScreenSpaceAmbientOcclusionQualityLevel::Ultra,
+                    },
+                    ..default()
+                },
+                TemporalAntiAliasBundle::default(),
+            )),
+            Some(layer) => commands.spawn((
+                Camera3dBundle {
+                    camera,
+                    camera_3d: Camera3d {
+                        clear_color: ClearColorConfig::Custom(Color::NONE),
+                        ..default()
+                    },
+                    tonemapping: Tonemapping::None,
+                    dither: DebandDither::Disabled,
+                    ..default()
+                },
+                RenderLayers::layer(layer),
+            )),
+        };
+        camera.insert(PassCamera);
+        let camera = camera.id();
+        commands.entity(preview).add_child(camera);
+
+        commands.spawn((
+            ImageExportBundle {
+                source: export_sources.add(texture.into()),
+                settings: ImageExportSettings {
+                    output_dir: String::new(),
+                    extension: pass.extension().into(),
+                    render: false,
+                },
+            },
+            PassExport(*pass),
+        ));
+    }
+}
+
+// pass exporters export exactly the frames the beauty exporter exports
+pub fn follow_beauty_export(
+    beauty: Query<&ImageExportSettings, Without<PassExport>>,
+    mut passes: Query<(&PassExport, &mut ImageExportSettings)>,
+) {
+    let Ok(beauty) = beauty.get_single() else {
+        return;
+    };
+    for (PassExport(pass), mut settings) in passes.iter_mut() {
+        let output_dir = pass.dir(&beauty.output_dir).to_string_lossy().into_owned();
+        if settings.output_dir != output_dir {
+            settings.output_dir = output_dir;
+        }
+        if settings.render != beauty.render {
+            settings.render = beauty.render;
+        }
+    }
+}
+
+// keeps a stand-in per cube for every enabled layered pass
+pub fn spawn_pass_proxies(
+    mut commands: Commands,
+    passes: Res<Passes>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    cubes: Query<(Entity, &AutoCube, &Handle<Mesh>, Option<&Children>)>,
+    proxies: Query<(Entity, &PassProxy)>,
+) {
+    for (entity, PassProxy(pass)) in proxies.iter() {
+        if !passes.enabled.contains(pass) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    let layered: Vec<Pass> = passes
+        .enabled
+        .iter()
+        .copied()
+        .filter(|pass| pass.layer().is_some())
+        .collect();
+    if layered.is_empty() {
+        return;
+    }
+
+    for (entity, cube, mesh, children) in cubes.iter() {
+        let has = |pass: Pass| {
+            children.is_some_and(|children| {
+                children
+                    .iter()
+                    .any(|child| proxies.get(*child).is_ok_and(|(_, proxy)| proxy.0 == pass))
+            })
+        };
+        for pass in layered.iter().copied().filter(|pass| !has(*pass)) {
+            let (mesh, color) = match pass {
+                // colored per vertex by shade_pass_proxies
+                Pass::Depth | Pass::Normal => {
+                    let Some(mesh) = meshes.get(mesh).cloned() else {
+                        continue;
+                    };
+                    (meshes.add(mesh), Color::WHITE)
+                }
+                _ => (mesh.clone(), id_color(cube.index)),
+            };
+            let proxy = commands
+                .spawn((
+                    PbrBundle {
+                        mesh,
+                        material: materials.add(StandardMaterial {
+                            base_color: color,
+                            unlit: true,
+                            ..default()
+                        }),
+                        ..default()
+                    },
+                    RenderLayers::layer(pass.layer().unwrap_or_default()),
+                    PassProxy(pass),
+                ))
+                .id();
+            commands.entity(entity).add_child(proxy);
+        }
+    }
+}
+
+// writes view depth and view normals into the stand-ins' vertex colors,
+// only on frames that are exported so the meshes aren't uploaded every frame
+pub fn shade_pass_proxies(
+    mut meshes: ResMut<Assets<Mesh>>,
+    beauty: Query<&ImageExportSettings, Without<PassExport>>,
+    camera: Query<&GlobalTransform, With<ExportCamera>>,
+    proxies: Query<(&PassProxy, &Handle<Mesh>, &GlobalTransform)>,
+) {
+    if !beauty.get_single().is_ok_and(|beauty| beauty.render) {
+        return;
+    }
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+    let view = camera.compute_matrix().inverse();
+
+    for (PassProxy(pass), mesh, transform) in proxies.iter() {
+        if !matches!(pass, Pass::Depth | Pass::Normal) {
+            continue;
+        }
+        let Some(mesh) = meshes.get_mut(mesh) else {
+            continue;
+        };
+        let model_view = view * transform.compute_matrix();
+        let colors: Vec<[f32; 4]> = match pass {
+            Pass::Depth => match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+                Some(VertexAttributeValues::Float32x3(positions)) => positions
+                    .iter()
+                    .map(|position| {
+                        let depth = -model_view.transform_point3(Vec3::from(*position)).z;
+                        [depth, depth, depth, 1.]
+                    })
+                    .collect(),
+                _ => continue,
+            },
+            _ => match mesh.attribute(Mesh::ATTRIBUTE_NORMAL) {
+                Some(VertexAttributeValues::Float32x3(normals)) => normals
+                    .iter()
+                    .map(|normal| {
+                        let normal = model_view
+                            .transform_vector3(Vec3::from(*normal))
+                            .normalize_or_zero();
+                        let color = normal * 0.5 + 0.5;
+                        [color.x, color.y, color.z, 1.]
+                    })
+                    .collect(),
+                _ => continue,
+            },
+        };
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    }
+}
@@ -0,0 +1,247 @@
+use std::{fmt::Write, fs, path::Path};
+
+use bevy::prelude::*;
+
+use crate::{
+    export::{capture_name, ExportCamera, ExportSettings},
+    lines::{visible_lines, Polyline},
+    ortho::{collect_cubes, Projector},
+    simulation::SimRng,
+    AutoCube,
+};
+// pen plotter files of the hidden-line drawing
+// -- the visible edges come from lines::visible_lines, so the plot matches
+//    the line export exactly
+// -- every chunk is drawn with a pen, chunks sharing a pen are plotted
+//    together and pens go in ascending order
+// -- for each pen the paths are ordered greedily, always the nearest path
+//    end next, drawn backwards when that end is closer, to cut pen-up travel
+// -- canvas units become mm through the export dpi, origin bottom left, y up
+// -- hpgl selects pens with SP, g-code lifts the pen on z and pauses with M0
+//    for pen changes
+
+pub const PLOTTER_DIR: &str = "out/plotter";
+
+// hpgl plotter units per mm
+const HPGL_UNITS: f32 = 40.;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PlotterFormat {
+    Hpgl,
+    Gcode,
+}
+
+impl PlotterFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlotterFormat::Hpgl => "HPGL",
+            PlotterFormat::Gcode => "G-code",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PlotterFormat::Hpgl => "hpgl",
+            PlotterFormat::Gcode => "gcode",
+        }
+    }
+
+    pub fn all() -> [PlotterFormat; 2] {
+        [PlotterFormat::Hpgl, PlotterFormat::Gcode]
+    }
+}
+
+#[derive(Resource)]
+pub struct PlotterExport {
+    pub format: PlotterFormat,
+    // pen of each chunk by index, pen 1 past the end
+    pub pens: Vec<u32>,
+    // mm per minute while drawing, g-code only
+    pub feed_rate: f32,
+    pub pen_up_z: f32,
+    pub pen_down_z: f32,
+    pub requested: bool,
+}
+
+impl Default for PlotterExport {
+    fn default() -> Self {
+        PlotterExport {
+            format: PlotterFormat::Hpgl,
+            pens: Vec::new(),
+            feed_rate: 3000.,
+            pen_up_z: 2.,
+            pen_down_z: 0.,
+            requested: false,
+        }
+    }
+}
+
+impl PlotterExport {
+    pub fn pen(&self, index: usize) -> u32 {
+        self.pens.get(index).copied().unwrap_or(1)
+    }
+}
+
+// a pen's paths in mm, in plotting order
+pub struct PenPaths {
+    pub pen: u32,
+    pub paths: Vec<Vec<Vec2>>,
+}
+
+// paths in drawing order, nearest end first from `position`, which ends up
+// where the pen stops
+fn order_paths(mut paths: Vec<Vec<Vec2>>, position: &mut Vec2) -> Vec<Vec<Vec2>> {
+    let mut ordered = Vec::with_capacity(paths.len());
+    while !paths.is_empty() {
+        let mut best = (0, false, f32::MAX);
+        for (i, path) in paths.iter().enumerate() {
+            let start = position.distance_squared(path[0]);
+            let end = position.distance_squared(path[path.len() - 1]);
+            if start < best.2 {
+                best = (i, false, start);
+            }
+            if end < best.2 {
+                best = (i, true, end);
+            }
+        }
+        let mut path = paths.swap_remove(best.0);
+        if best.1 {
+            path.reverse();
+        }
+        *position = path[path.len() - 1];
+        ordered.push(path);
+    }
+    ordered
+}
+
+// pen-up distance from the origin through the paths and back
+fn travel(pens: &[PenPaths]) -> f32 {
+    let mut position = Vec2::ZERO;
+    let mut travel = 0.;
+    for path in pens.iter().flat_map(|pen| &pen.paths) {
+        travel += position.distance(path[0]);
+        position = path[path.len() - 1];
+    }
+    travel + position.length()
+}
+
+// the polylines in mm, grouped by pen and ordered for plotting,
+// with the pen-up travel in mm before ordering
+pub fn plot_paths(
+    polylines: &[Polyline],
+    size: Vec2,
+    dpi: f32,
+    plotter: &PlotterExport,
+) -> (Vec<PenPaths>, f32) {
+    let to_mm = |point: Vec2| Vec2::new(point.x, size.y - point.y) / dpi.max(1.) * 25.4;
+    let mut pens: Vec<PenPaths> = Vec::new();
+    for polyline in polylines
+        .iter()
+        .filter(|polyline| polyline.points.len() > 1)
+    {
+        let pen = plotter.pen(polyline.index);
+        let path = polyline.points.iter().map(|point| to_mm(*point)).collect();
+        match pens.iter_mut().find(|group| group.pen == pen) {
+            Some(group) => group.paths.push(path),
+            None => pens.push(PenPaths {
+                pen,
+                paths: vec![path],
+            }),
+        }
+    }
+    pens.sort_by_key(|group| group.pen);
+
+    let before = travel(&pens);
+    let mut position = Vec2::ZERO;
+    for group in pens.iter_mut() {
+        group.paths = order_paths(std::mem::take(&mut group.paths), &mut position);
+    }
+    (pens, before)
+}
+
+pub fn hpgl(pens: &[PenPaths]) -> String {
+    let unit = |point: &Vec2| {
+        let point = (*point * HPGL_UNITS).round();
+        format!("{},{}", point.x as i64, point.y as i64)
+    };
+    let mut hpgl = String::from("IN;\n");
+    for pen in pens {
+        let _ = writeln!(hpgl, "SP{};", pen.pen);
+        for path in &pen.paths {
+            let _ = writeln!(hpgl, "PU{};", unit(&path[0]));
+            let points = path[1..].iter().map(unit).collect::<Vec<_>>().join(",");
+            let _ = writeln!(hpgl, "PD{};", points);
+        }
+    }
+    hpgl.push_str("PU0,0;\nSP0;\n");
+    hpgl
+}
+
+pub fn gcode(pens: &[PenPaths], plotter: &PlotterExport) -> String {
+    let mut gcode = String::from("G21\nG90\n");
+    let up = format!("G0 Z{:.2}\n", plotter.pen_up_z);
+    gcode.push_str(&up);
+    for (i, pen) in pens.iter().enumerate() {
+        if i > 0 {
+            let _ = writeln!(gcode, "G0 X0 Y0\nM0 (change to pen {})", pen.pen);
+        } else {
+            let _ = writeln!(gcode, "(pen {})", pen.pen);
+        }
+        for path in &pen.paths {
+            let _ = writeln!(gcode, "G0 X{:.3} Y{:.3}", path[0].x, path[0].y);
+            let _ = writeln!(
+                gcode,
+                "G1 Z{:.2} F{:.0}",
+                plotter.pen_down_z, plotter.feed_rate
+            );
+            for point in &path[1..] {
+                let _ = writeln!(gcode, "G1 X{:.3} Y{:.3}", point.x, point.y);
+            }
+            gcode.push_str(&up);
+        }
+    }
+    gcode.push_str("G0 X0 Y0\n");
+    gcode
+}
+
+pub fn export_plotter(
+    mut plotter: ResMut<PlotterExport>,
+    export_settings: Res<ExportSettings>,
+    sim_rng: Res<SimRng>,
+    materials: Res<Assets<StandardMaterial>>,
+    camera: Query<(&GlobalTransform, &Projection), With<ExportCamera>>,
+    cubes: Query<(&AutoCube, &GlobalTransform, &Handle<StandardMaterial>)>,
+) {
+    if !plotter.requested {
+        return;
+    }
+    plotter.requested = false;
+
+    let Ok((transform, projection)) = camera.get_single() else {
+        return;
+    };
+    let size = Vec2::new(export_settings.width as f32, export_settings.height as f32);
+    let Some(projector) = Projector::new(transform, projection, size) else {
+        warn!("plotter export needs an orthographic camera");
+        return;
+    };
+
+    let polylines = visible_lines(&projector, &collect_cubes(&cubes, &materials));
+    let (pens, unordered) = plot_paths(&polylines, size, export_settings.dpi, &plotter);
+    let document = match plotter.format {
+        PlotterFormat::Hpgl => hpgl(&pens),
+        PlotterFormat::Gcode => gcode(&pens, &plotter),
+    };
+    let file = Path::new(PLOTTER_DIR)
+        .join(capture_name(sim_rng.seed))
+        .with_extension(plotter.format.extension());
+    match fs::create_dir_all(PLOTTER_DIR).and_then(|_| fs::write(&file, document)) {
+        Ok(_) => println!(
+            "exported {:?}, pen up travel {:.0} mm, {:.0} mm before ordering",
+            file,
+            travel(&pens),
+            unordered
+        ),
+        Err(err) => warn!("could not write {:?}: {}", file, err),
+    }
+}
@@ -0,0 +1,334 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use bevy_image_export::ImageExportSettings;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    export::{capture_name, ExportSettings},
+    passes::{Pass, PassExport, Passes},
+    scene::{CameraState, Scene},
+    setup::PlisCamera,
+    simulation::{SimRng, Simulation},
+    ChunkStates,
+};
+// frame sequences locked to the simulation
+// -- the simulation is paused and moved by a fixed amount of virtual time per tick
+// -- after each tick the export camera gets `settle` frames, then exactly
+//    `frames_per_tick` frames are exported
+// -- the camera can orbit the origin (turntable) or follow keyframes,
+//    with `grow` off the simulation stays frozen and only the camera moves
+// -- the exporter writes into a scratch folder, at the end the frames are
+//    renumbered 00000.<ext>.. into out/sequences/<timestamp>_seed<seed>/
+//    next to a scene.json, frames of the enabled passes get the same number
+//    as 00000.<pass>.<ext>..
+// -- that scene.json notes the recording settings and the camera motion
+//    next to the start camera, for the record, nothing reads them back
+
+pub const SEQUENCES_DIR: &str = "out/sequences";
+// frames to wait for the exporter to finish writing before giving up
+const COLLECT_TIMEOUT: u32 = 600;
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum CameraMotion {
+    Still,
+    // full `degrees` around `axis` through the origin over the recording
+    Turntable { axis: Vec3, degrees: f32 },
+    // evenly spaced keys, first key on the first tick, last on the last
+    Path(Vec<CameraState>),
+}
+
+impl CameraMotion {
+    pub fn pose(&self, start: &CameraState, tick: u32, ticks: u32) -> CameraState {
+        match self {
+            CameraMotion::Still => start.clone(),
+            CameraMotion::Turntable { axis, degrees } => {
+                let t = tick as f32 / ticks.max(1) as f32;
+                let angle =
+                    Quat::from_axis_angle(axis.normalize_or_zero(), degrees.to_radians() * t);
+                let mut transform = Transform {
+                    translation: start.translation,
+                    rotation: start.rotation,
+                    ..default()
+                };
+                transform.rotate_around(Vec3::ZERO, angle);
+                CameraState {
+                    translation: transform.translation,
+                    rotation: transform.rotation,
+                    scale: start.scale,
+                }
+            }
+            CameraMotion::Path(keys) => match keys.len() {
+                0 => start.clone(),
+                1 => keys[0].clone(),
+                len => {
+                    let t = tick as f32 / ticks.saturating_sub(1).max(1) as f32;
+                    let segment = (t * (len - 1) as f32).clamp(0.0, (len - 1) as f32);
+                    let index = (segment.floor() as usize).min(len - 2);
+                    keys[index].lerp(&keys[index + 1], segment - index as f32)
+                }
+            },
+        }
+    }
+}
+
+// what a sequence was recorded with, noted in its scene.json
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SequenceSettings {
+    pub ticks: u32,
+    pub tick_seconds: f32,
+    pub frames_per_tick: u32,
+    pub settle: u32,
+    pub camera: CameraMotion,
+    pub grow: bool,
+}
+
+#[derive(Resource)]
+pub struct Recording {
+    pub ticks: u32,
+    // virtual seconds the simulation moves per tick
+    pub tick_seconds: f32,
+    pub frames_per_tick: u32,
+    // frames rendered but not exported after each tick, lets taa settle
+    pub settle: u32,
+    pub camera: CameraMotion,
+    // false freezes the simulation, only the camera moves
+    pub grow: bool,
+    pub requested: bool,
+    state: RecordState,
+}
+
+impl Default for Recording {
+    fn default() -> Self {
+        Recording {
+            ticks: 120,
+            tick_seconds: 0.8,
+            frames_per_tick: 1,
+            settle: 4,
+            camera: CameraMotion::Still,
+            grow: true,
+            requested: false,
+            state: RecordState::Idle,
+        }
+    }
+}
+
+impl Recording {
+    pub fn settings(&self) -> SequenceSettings {
+        SequenceSettings {
+            ticks: self.ticks,
+            tick_seconds: self.tick_seconds,
+            frames_per_tick: self.frames_per_tick,
+            settle: self.settle,
+            camera: self.camera.clone(),
+            grow: self.grow,
+        }
+    }
+
+    pub fn busy(&self) -> bool {
+        !matches!(self.state, RecordState::Idle)
+    }
+
+    // (ticks done, ticks total)
+    pub fn progress(&self) -> Option<(u32, u32)> {
+        match &self.state {
+            RecordState::Idle => None,
+            RecordState::Tick(sequence)
+            | RecordState::Settle(sequence, _)
+            | RecordState::Export(sequence, _) => Some((sequence.tick, sequence.ticks)),
+            RecordState::Collect(sequence, _) => Some((sequence.ticks, sequence.ticks)),
+        }
+    }
+}
+
+struct Sequence {
+    dir: PathBuf,
+    scratch: PathBuf,
+    ticks: u32,
+    tick: u32,
+    frames_per_tick: u32,
+    settle: u32,
+    tick_seconds: f32,
+    camera: CameraMotion,
+    grow: bool,
+    // exporter, simulation and camera state from before the recording
+    output_dir: String,
+    was_paused: bool,
+    start: CameraState,
+    passes: Vec<Pass>,
+}
+
+enum RecordState {
+    Idle,
+    Tick(Sequence),
+    Settle(Sequence, u32),
+    Export(Sequence, u32),
+    Collect(Sequence, u32),
+}
+
+pub fn record_sequence(
+    mut recording: ResMut<Recording>,
+    mut simulation: ResMut<Simulation>,
+    sim_rng: Res<SimRng>,
+    chunk_states: Res<ChunkStates>,
+    export_settings: Res<ExportSettings>,
+    passes: Res<Passes>,
+    mut camera: Query<(&mut Transform, &mut Projection), With<PlisCamera>>,
+    mut render: Query<&mut ImageExportSettings, Without<PassExport>>,
+) {
+    let Ok(mut render) = render.get_single_mut() else {
+        return;
+    };
+    let Ok((mut transform, mut projection)) = camera.get_single_mut() else {
+        return;
+    };
+
+    let state = std::mem::replace(&mut recording.state, RecordState::Idle);
+    recording.state = match state {
+        RecordState::Idle => {
+            if !recording.requested {
+                return;
+            }
+            recording.requested = false;
+
+            let start = CameraState::new(&transform, &projection);
+            let dir = Path::new(SEQUENCES_DIR).join(capture_name(sim_rng.seed));
+            let scratch = dir.join(".raw");
+            if let Err(err) = fs::create_dir_all(&scratch) {
+                warn!("could not create {:?}: {}", scratch, err);
+                return;
+            }
+            let scene = Scene {
+                seed: sim_rng.seed,
+                tick: simulation.tick,
+                export: export_settings.clone(),
+                camera: start.clone(),
+                chunks: chunk_states.0.clone(),
+                parameters: default(),
+                sequence: Some(recording.settings()),
+            };
+            if let Err(err) = scene.write(dir.join("scene.json")) {
+                warn!("could not write the scene for {:?}: {}", dir, err);
+            }
+
+            let sequence = Sequence {
+                dir,
+                output_dir: render.output_dir.clone(),
+                scratch,
+                ticks: recording.ticks,
+                tick: 0,
+                frames_per_tick: recording.frames_per_tick.max(1),
+                settle: recording.settle,
+                tick_seconds: recording.tick_seconds,
+                camera: recording.camera.clone(),
+                grow: recording.grow,
+                was_paused: simulation.paused,
+                start,
+                passes: passes.enabled.clone(),
+            };
+            simulation.paused = true;
+            render.render = false;
+            render.output_dir = sequence.scratch.to_string_lossy().into_owned();
+            RecordState::Tick(sequence)
+        }
+        RecordState::Tick(sequence) if sequence.tick >= sequence.ticks => {
+            RecordState::Collect(sequence, 0)
+        }
+        RecordState::Tick(mut sequence) => {
+            let pose = sequence
+                .camera
+                .pose(&sequence.start, sequence.tick, sequence.ticks);
+            pose.apply(&mut transform, &mut projection);
+            if sequence.grow {
+                simulation.advance = sequence.tick_seconds;
+            }
+            sequence.tick += 1;
+            // the new pose and cubes render on the next frame, settle at least one
+            let settle = sequence.settle.max(1);
+            RecordState::Settle(sequence, settle)
+        }
+        RecordState::Settle(sequence, 0) => {
+            render.render = true;
+            let frames = sequence.frames_per_tick;
+            RecordState::Export(sequence, frames)
+        }
+        RecordState::Settle(sequence, frames) => RecordState::Settle(sequence, frames - 1),
+        RecordState::Export(sequence, frames) if frames <= 1 => {
+            render.render = false;
+            RecordState::Tick(sequence)
+        }
+        RecordState::Export(sequence, frames) => RecordState::Export(sequence, frames - 1),
+        RecordState::Collect(sequence, frames) => {
+            let written = raw_frames(&sequence.scratch, &render.extension);
+            let pass_written: Vec<(Pass, Vec<PathBuf>)> = sequence
+                .passes
+                .iter()
+                .map(|pass| {
+                    let dir = pass.dir(&sequence.scratch);
+                    (*pass, raw_frames(&dir, pass.extension()))
+                })
+                .collect();
+            let expected = (sequence.ticks * sequence.frames_per_tick) as usize;
+            let complete = written.len() >= expected
+                && pass_written
+                    .iter()
+                    .all(|(_, frames)| frames.len() >= expected);
+
+            if !complete && frames < COLLECT_TIMEOUT {
+                RecordState::Collect(sequence, frames + 1)
+            } else {
+                if written.len() < expected {
+                    warn!("only {} of {} frames were written", written.len(), expected);
+                }
+                for (index, frame) in written.iter().enumerate() {
+                    let numbered = sequence
+                        .dir
+                        .join(format!("{:05}", index))
+                        .with_extension(&render.extension);
+                    if let Err(err) = fs::rename(frame, &numbered) {
+                        warn!("could not move {:?}: {}", frame, err);
+                    }
+                }
+                for (pass, frames) in pass_written {
+                    if frames.len() < expected {
+                        warn!(
+                            "only {} of {} {} frames were written",
+                            frames.len(),
+                            expected,
+                            pass.name()
+                        );
+                    }
+                    for (index, frame) in frames.iter().enumerate() {
+                        let numbered = pass.file(sequence.dir.join(format!("{:05}", index)));
+                        if let Err(err) = fs::rename(frame, &numbered) {
+                            warn!("could not move {:?}: {}", frame, err);
+                        }
+                    }
+                }
+                println!("recorded {} frames to {:?}", written.len(), sequence.dir);
+
+                let _ = fs::remove_dir_all(&sequence.scratch);
+                sequence.start.apply(&mut transform, &mut projection);
+                render.output_dir = sequence.output_dir;
+                simulation.paused = sequence.was_paused;
+                RecordState::Idle
+            }
+        }
+    };
+}
+
+// frames in the scratch folder in the order the exporter wrote them
+pub fn raw_frames(scratch: &Path, extension: &str) -> Vec<PathBuf> {
+    let mut frames: Vec<PathBuf> = fs::read_dir(scratch)
+        .map(|dir| {
+            dir.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == extension))
+                .collect()
+        })
+        .unwrap_or_default();
+    frames.sort();
+    frames
+}
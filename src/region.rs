@@ -0,0 +1,351 @@
+use std::borrow::Cow;
+
+use bevy::prelude::*;
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::{Bounds, ChunkState};
+// the shape a chunk grows in
+// -- in bounds units like Bounds, positions are multiplied by the chunk scale
+// -- a chunk without a region grows in its bounds box, the box is the same
+//    test the per axis clamping used to do
+// -- a random step that leaves the region is clamped to the region's box
+//    first, when that isn't inside either the step is cut short at the
+//    region's edge, and a walker that is outside altogether jumps to a
+//    random inside point
+// -- composites are written in the scene file:
+//    { "Difference": [{ "Sphere": { "center": [0, 0, 0], "radius": 3 } },
+//                     { "Cylinder": { "base": [0, 0, -3], "axis": [0, 0, 6], "radius": 1 } }] }
+
+// tries to find an inside point by chance before giving up
+pub const SAMPLES: usize = 1000;
+
+// halvings of a step to find the region's edge
+const BISECTIONS: usize = 12;
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Region {
+    Cuboid {
+        min: Vec3,
+        max: Vec3,
+    },
+    Sphere {
+        center: Vec3,
+        radius: f32,
+    },
+    // from `base` to `base + axis`
+    Cylinder {
+        base: Vec3,
+        axis: Vec3,
+        radius: f32,
+    },
+    // a polygon in xy, from min_z to max_z
+    Extrusion {
+        points: Vec<Vec2>,
+        min_z: f32,
+        max_z: f32,
+    },
+    Union(Vec<Region>),
+    // inside the first and not the second
+    Difference(Box<Region>, Box<Region>),
+}
+
+impl From<&Bounds> for Region {
+    fn from(bounds: &Bounds) -> Self {
+        Region::Cuboid {
+            min: bounds.min,
+            max: bounds.max,
+        }
+    }
+}
+
+impl Region {
+    // one of each primitive fitted into the bounds, a starting point for the ui
+    pub fn primitives(bounds: &Bounds) -> [Region; 4] {
+        let (min, max) = (bounds.min.min(bounds.max), bounds.min.max(bounds.max));
+        let center = (min + max) / 2.;
+        let radius = (max - min).truncate().min_element() / 2.;
+        [
+            Region::from(bounds),
+            Region::Sphere { center, radius },
+            Region::Cylinder {
+                base: Vec3::new(center.x, center.y, min.z),
+                axis: Vec3::Z * (max.z - min.z),
+                radius,
+            },
+            // a diamond through the middles of the sides
+            Region::Extrusion {
+                points: vec![
+                    Vec2::new(center.x, min.y),
+                    Vec2::new(max.x, center.y),
+                    Vec2::new(center.x, max.y),
+                    Vec2::new(min.x, center.y),
+                ],
+                min_z: min.z,
+                max_z: max.z,
+            },
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Region::Cuboid { .. } => "Box",
+            Region::Sphere { .. } => "Sphere",
+            Region::Cylinder { .. } => "Cylinder",
+            Region::Extrusion { .. } => "Extrusion",
+            Region::Union(_) => "Union",
+            Region::Difference(_, _) => "Difference",
+        }
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        match self {
+            Region::Cuboid { min, max } => {
+                let (low, high) = (min.min(*max), min.max(*max));
+                point.cmpge(low).all() && point.cmple(high).all()
+            }
+            Region::Sphere { center, radius } => point.distance_squared(*center) <= radius * radius,
+            Region::Cylinder { base, axis, radius } => {
+                let length = axis.length_squared();
+                if length <= 0. {
+                    return false;
+                }
+                let t = (point - *base).dot(*axis) / length;
+                let radial = point - (*base + *axis * t);
+                (0.0..=1.0).contains(&t) && radial.length_squared() <= radius * radius
+            }
+            Region::Extrusion {
+                points,
+                min_z,
+                max_z,
+            } => {
+                let (low, high) = (min_z.min(*max_z), min_z.max(*max_z));
+                (low..=high).contains(&point.z) && in_polygon(points, point.truncate())
+            }
+            Region::Union(regions) => regions.iter().any(|region| region.contains(point)),
+            Region::Difference(keep, cut) => keep.contains(point) && !cut.contains(point),
+        }
+    }
+
+    // a box around the region
+    pub fn aabb(&self) -> (Vec3, Vec3) {
+        match self {
+            Region::Cuboid { min, max } => (min.min(*max), min.max(*max)),
+            Region::Sphere { center, radius } => (*center - *radius, *center + *radius),
+            Region::Cylinder { base, axis, radius } => {
+                let top = *base + *axis;
+                (base.min(top) - *radius, base.max(top) + *radius)
+            }
+            Region::Extrusion {
+                points,
+                min_z,
+                max_z,
+            } => {
+                if points.is_empty() {
+                    return (Vec3::ZERO, Vec3::ZERO);
+                }
+                let low = points
+                    .iter()
+                    .copied()
+                    .fold(Vec2::splat(f32::MAX), Vec2::min);
+                let high = points
+                    .iter()
+                    .copied()
+                    .fold(Vec2::splat(f32::MIN), Vec2::max);
+                (
+                    low.extend(min_z.min(*max_z)),
+                    high.extend(min_z.max(*max_z)),
+                )
+            }
+            Region::Union(regions) => regions
+                .iter()
+                .map(Region::aabb)
+                .reduce(|(a_min, a_max), (b_min, b_max)| (a_min.min(b_min), a_max.max(b_max)))
+                .unwrap_or((Vec3::ZERO, Vec3::ZERO)),
+            Region::Difference(keep, _) => keep.aabb(),
+        }
+    }
+
+    // a random inside point, None when none turns up
+    pub fn sample(&self, rng: &mut dyn RngCore) -> Option<Vec3> {
+        let (min, max) = self.aabb();
+        (0..SAMPLES)
+            .map(|_| random_in_box(min, max, rng))
+            .find(|point| self.contains(*point))
+    }
+
+    // where a step from `from` to `to` ends up, see the top
+    pub fn constrain(&self, from: Vec3, to: Vec3, rng: &mut dyn RngCore) -> Vec3 {
+        if self.contains(to) {
+            return to;
+        }
+        let (min, max) = self.aabb();
+        let clamped = to.clamp(min, max);
+        if self.contains(clamped) {
+            return clamped;
+        }
+        if self.contains(from) {
+            let (mut inside, mut outside) = (from, to);
+            for _ in 0..BISECTIONS {
+                let middle = inside.lerp(outside, 0.5);
+                match self.contains(middle) {
+                    true => inside = middle,
+                    false => outside = middle,
+                }
+            }
+            return inside;
+        }
+        self.sample(rng).unwrap_or(clamped)
+    }
+}
+
+impl ChunkState {
+    // where the chunk grows, its bounds box unless it has a region
+    pub fn region(&self) -> Cow<'_, Region> {
+        match &self.region {
+            Some(region) => Cow::Borrowed(region),
+            None => Cow::Owned(Region::from(&self.bounds)),
+        }
+    }
+}
+
+// even-odd rule
+fn in_polygon(points: &[Vec2], point: Vec2) -> bool {
+    let mut inside = false;
+    for i in 0..points.len() {
+        let (a, b) = (points[i], points[(i + 1) % points.len()]);
+        if (a.y > point.y) != (b.y > point.y)
+            && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x)
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+pub fn random_in_box(min: Vec3, max: Vec3, rng: &mut dyn RngCore) -> Vec3 {
+    let mut axis = |min: f32, max: f32| {
+        if min < max {
+            rng.gen_range(min..max)
+        } else {
+            min
+        }
+    };
+    Vec3::new(axis(min.x, max.x), axis(min.y, max.y), axis(min.z, max.z))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cuboid_with_either_corner_first() {
+        let cuboid = Region::Cuboid {
+            min: Vec3::new(2., 2., 2.),
+            max: Vec3::ZERO,
+        };
+        assert!(cuboid.contains(Vec3::ONE));
+        assert!(cuboid.contains(Vec3::new(2., 0., 2.)));
+        assert!(!cuboid.contains(Vec3::new(1., 1., 2.1)));
+    }
+
+    #[test]
+    fn sphere() {
+        let sphere = Region::Sphere {
+            center: Vec3::ONE,
+            radius: 2.,
+        };
+        assert!(sphere.contains(Vec3::new(1., 1., 3.)));
+        assert!(!sphere.contains(Vec3::new(3., 3., 1.)));
+    }
+
+    #[test]
+    fn cylinder_along_its_axis() {
+        let cylinder = Region::Cylinder {
+            base: Vec3::ZERO,
+            axis: Vec3::X * 4.,
+            radius: 1.,
+        };
+        assert!(cylinder.contains(Vec3::new(2., 0.5, 0.5)));
+        assert!(!cylinder.contains(Vec3::new(2., 1., 1.)));
+        assert!(!cylinder.contains(Vec3::new(-0.1, 0., 0.)));
+        assert!(!cylinder.contains(Vec3::new(4.1, 0., 0.)));
+
+        let flat = Region::Cylinder {
+            base: Vec3::ZERO,
+            axis: Vec3::ZERO,
+            radius: 1.,
+        };
+        assert!(!flat.contains(Vec3::ZERO));
+    }
+
+    #[test]
+    fn extrusion_of_a_concave_polygon() {
+        // a U open at the top
+        let extrusion = Region::Extrusion {
+            points: vec![
+                Vec2::new(0., 0.),
+                Vec2::new(3., 0.),
+                Vec2::new(3., 3.),
+                Vec2::new(2., 3.),
+                Vec2::new(2., 1.),
+                Vec2::new(1., 1.),
+                Vec2::new(1., 3.),
+                Vec2::new(0., 3.),
+            ],
+            min_z: 1.,
+            max_z: -1.,
+        };
+        assert!(extrusion.contains(Vec3::new(0.5, 2., 0.)));
+        assert!(extrusion.contains(Vec3::new(1.5, 0.5, 0.)));
+        assert!(!extrusion.contains(Vec3::new(1.5, 2., 0.)));
+        assert!(!extrusion.contains(Vec3::new(0.5, 2., 1.5)));
+        assert!(!extrusion.contains(Vec3::new(4., 0.5, 0.)));
+    }
+
+    #[test]
+    fn union_and_difference() {
+        let a = Region::Sphere {
+            center: Vec3::ZERO,
+            radius: 1.,
+        };
+        let b = Region::Sphere {
+            center: Vec3::X * 3.,
+            radius: 1.,
+        };
+        let union = Region::Union(vec![a.clone(), b.clone()]);
+        assert!(union.contains(Vec3::ZERO));
+        assert!(union.contains(Vec3::X * 3.));
+        assert!(!union.contains(Vec3::X * 1.5));
+        assert!(!Region::Union(Vec::new()).contains(Vec3::ZERO));
+
+        let hole = Region::Sphere {
+            center: Vec3::ZERO,
+            radius: 0.5,
+        };
+        let shell = Region::Difference(Box::new(a), Box::new(hole));
+        assert!(!shell.contains(Vec3::ZERO));
+        assert!(shell.contains(Vec3::X * 0.75));
+        assert!(!shell.contains(Vec3::X * 1.5));
+    }
+
+    #[test]
+    fn constrain_stays_inside() {
+        let cuboid = Region::Cuboid {
+            min: Vec3::ZERO,
+            max: Vec3::ONE,
+        };
+        let mut rng = rand::thread_rng();
+        let to = Vec3::new(2., 0.5, 0.5);
+        assert_eq!(
+            cuboid.constrain(Vec3::splat(0.5), to, &mut rng),
+            Vec3::new(1., 0.5, 0.5)
+        );
+        let sphere = Region::Sphere {
+            center: Vec3::ZERO,
+            radius: 1.,
+        };
+        let end = sphere.constrain(Vec3::ZERO, Vec3::splat(2.), &mut rng);
+        assert!(sphere.contains(end));
+    }
+}
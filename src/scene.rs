@@ -0,0 +1,118 @@
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    export::ExportSettings,
+    record::SequenceSettings,
+    setup::{CAMERA_SCALE, CAMERA_TRANSLATION},
+    ChunkState,
+};
+// everything needed to grow and frame a composition again
+// -- written next to every capture as a sidecar
+// -- json so it can be read and edited by hand
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct CameraState {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: f32,
+}
+
+impl CameraState {
+    pub fn new(transform: &Transform, projection: &Projection) -> Self {
+        let scale = match projection {
+            Projection::Orthographic(orthographic) => orthographic.scale,
+            Projection::Perspective(_) => 1.,
+        };
+        CameraState {
+            translation: transform.translation,
+            rotation: transform.rotation,
+            scale,
+        }
+    }
+
+    pub fn apply(&self, transform: &mut Transform, projection: &mut Projection) {
+        transform.translation = self.translation;
+        transform.rotation = self.rotation;
+        if let Projection::Orthographic(ref mut orthographic) = *projection {
+            orthographic.scale = self.scale;
+        }
+    }
+
+    pub fn lerp(&self, other: &CameraState, t: f32) -> CameraState {
+        CameraState {
+            translation: self.translation.lerp(other.translation, t),
+            rotation: self.rotation.slerp(other.rotation, t),
+            scale: self.scale + (other.scale - self.scale) * t,
+        }
+    }
+}
+
+// the camera setup starts with
+impl Default for CameraState {
+    fn default() -> Self {
+        CameraState {
+            translation: CAMERA_TRANSLATION,
+            rotation: Quat::IDENTITY,
+            scale: CAMERA_SCALE,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Scene {
+    pub seed: u64,
+    // simulation steps since the reset, headless render grows this many
+    pub tick: u64,
+    pub export: ExportSettings,
+    pub camera: CameraState,
+    pub chunks: Vec<ChunkState>,
+    // values a batch sweep set for this scene
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub parameters: BTreeMap<String, Value>,
+    // how the sequence next to this scene was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<SequenceSettings>,
+}
+
+impl Scene {
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json)
+    }
+
+    // a scene without chunks is refused, there'd be nothing to grow
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Scene> {
+        let json = fs::read_to_string(path)?;
+        let scene: Scene = serde_json::from_str(&json)?;
+        if scene.chunks.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the scene has no chunks",
+            ));
+        }
+        Ok(scene)
+    }
+
+    // the scene imports build on, the file's or an empty one with the
+    // default camera and export
+    pub fn base(path: Option<&Path>) -> Result<Scene, String> {
+        match path {
+            Some(path) => {
+                Scene::read(path).map_err(|err| format!("could not read {:?}: {}", path, err))
+            }
+            None => Ok(Scene {
+                seed: rand::random(),
+                tick: 0,
+                export: default(),
+                camera: CameraState::default(),
+                chunks: Vec::new(),
+                parameters: default(),
+                sequence: None,
+            }),
+        }
+    }
+}
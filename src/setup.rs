@@ -7,48 +7,29 @@ use bevy::{
     prelude::*,
     render::{
         camera::{RenderTarget, ScalingMode},
-        render_resource::{
-            Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
-        },
+        render_resource::TextureFormat,
     },
 };
 use bevy_image_export::{ImageExportBundle, ImageExportSettings, ImageExportSource};
 use bevy_panorbit_camera::PanOrbitCamera;
+
+use crate::export::{export_texture, ExportCamera, ExportSettings};
 #[derive(Component)]
 pub struct PlisCamera;
 
+// where the camera starts, looking at the xy plane
+pub const CAMERA_TRANSLATION: Vec3 = Vec3::new(120.0, 880.0, 420.0);
+pub const CAMERA_SCALE: f32 = 1620.0;
+
 pub fn setup(
     mut commands: Commands,
     mut images: ResMut<Assets<Image>>,
     mut export_sources: ResMut<Assets<ImageExportSource>>,
+    export_settings: Res<ExportSettings>,
 ) {
     // camera
-    let output_texture_handle = {
-        let size = Extent3d {
-            width: 1584 * 2,
-            height: 1584 * 2,
-            ..default()
-        };
-        let mut export_texture = Image {
-            texture_descriptor: TextureDescriptor {
-                label: None,
-                size,
-                dimension: TextureDimension::D2,
-                format: TextureFormat::Rgba8UnormSrgb,
-                mip_level_count: 1,
-                sample_count: 1,
-                usage: TextureUsages::COPY_DST
-                    | TextureUsages::COPY_SRC
-                    | TextureUsages::RENDER_ATTACHMENT
-                    | TextureUsages::TEXTURE_BINDING,
-                view_formats: &[],
-            },
-            ..default()
-        };
-        export_texture.resize(size);
-
-        images.add(export_texture)
-    };
+    let output_texture_handle =
+        export_texture(&mut images, &export_settings, TextureFormat::Rgba8UnormSrgb);
 
     // camera
     commands.spawn(DirectionalLightBundle {
@@ -65,7 +46,7 @@ pub fn setup(
     commands
         .spawn((Camera3dBundle {
             projection: OrthographicProjection {
-                scale: 1620.0,
+                scale: CAMERA_SCALE,
                 scaling_mode: ScalingMode::FixedVertical(1.0),
                 far: 5000.0,
                 near: 0.0,
@@ -77,7 +58,7 @@ pub fn setup(
                 ..Default::default()
             },
             transform: Transform {
-                translation: Vec3::new(120.0, 880.0, 420.0),
+                translation: CAMERA_TRANSLATION,
                 rotation: Quat::from_xyzw(-0., 0., 0., 1.),
                 ..default()
             },
@@ -110,7 +91,8 @@ pub fn setup(
                     },
                     ..default()
                 })
-                .insert(TemporalAntiAliasBundle::default());
+                .insert(TemporalAntiAliasBundle::default())
+                .insert(ExportCamera);
         })
         .insert(ScreenSpaceAmbientOcclusionBundle {
             settings: ScreenSpaceAmbientOcclusionSettings {
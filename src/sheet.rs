@@ -0,0 +1,267 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use image::{
+    imageops::{self, FilterType},
+    Rgba, RgbaImage,
+};
+use serde_json::Value;
+
+use crate::{headless::parse_option, passes::Pass, scene::Scene};
+// contact sheets of exported frames, to review batches and sequences
+//   design_to_blocks sheet <dir> [--columns N] [--width PX] [--out FILE]
+// -- every png/jpg frame of the folder becomes a thumbnail, in name order,
+//    pass frames (<frame>.<pass>.<ext>) are left out
+// -- the label under a thumbnail comes from the frame's sidecar, or from the
+//    folder's scene.json for sequences: name, seed and tick, then the
+//    parameters a batch sweep set
+// -- a sequence frame shows its index and the start tick plus the
+//    recording ticks before it, frame / frames_per_tick
+// -- labels use a small pixel font, no font files needed
+
+pub const SHEET_NAME: &str = "contact_sheet.png";
+
+const BACKGROUND: Rgba<u8> = Rgba([24, 24, 24, 255]);
+const TEXT: Rgba<u8> = Rgba([220, 220, 220, 255]);
+const PADDING: u32 = 8;
+
+#[derive(Clone, Debug)]
+pub struct SheetArgs {
+    pub dir: PathBuf,
+    pub columns: u32,
+    // thumbnail width in pixels
+    pub width: u32,
+    // <dir>/contact_sheet.png when not given
+    pub out: Option<PathBuf>,
+}
+
+impl SheetArgs {
+    // the arguments after `sheet`
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<SheetArgs, String> {
+        let dir = args.next().ok_or("missing frame folder")?;
+        let mut sheet = SheetArgs {
+            dir: dir.into(),
+            columns: 6,
+            width: 320,
+            out: None,
+        };
+        while let Some(flag) = args.next() {
+            let value = args.next().ok_or(format!("missing value for {}", flag))?;
+            match flag.as_str() {
+                "--columns" => sheet.columns = parse_option(&flag, &value)?,
+                "--width" => sheet.width = parse_option(&flag, &value)?,
+                "--out" => sheet.out = Some(value.into()),
+                _ => return Err(format!("unknown option {}", flag)),
+            }
+        }
+        Ok(sheet)
+    }
+}
+
+// frames of the folder in name order
+fn frames(dir: &Path) -> Vec<PathBuf> {
+    let mut frames: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|dir| {
+            dir.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| {
+                    let extension = path
+                        .extension()
+                        .map(|ext| ext.to_string_lossy().to_lowercase());
+                    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                    let pass = Pass::all()
+                        .iter()
+                        .any(|pass| stem.ends_with(&format!(".{}", pass.name())));
+                    matches!(extension.as_deref(), Some("png" | "jpg" | "jpeg"))
+                        && !pass
+                        && path.file_name().is_some_and(|name| name != SHEET_NAME)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    frames.sort();
+    frames
+}
+
+fn label(frame: &Path) -> Vec<String> {
+    let stem = frame.file_stem().unwrap_or_default().to_string_lossy();
+    let mut lines = vec![stem.to_string()];
+    let own = Scene::read(frame.with_extension("json")).ok();
+    let scene = own
+        .clone()
+        .or_else(|| Scene::read(frame.with_file_name("scene.json")).ok());
+    if let Some(scene) = scene {
+        // frames of a sequence share the scene of its start, numbered from 0
+        let sequence = scene.sequence.as_ref().filter(|_| own.is_none());
+        match (sequence, stem.parse::<u32>()) {
+            (Some(sequence), Ok(index)) => lines.push(format!(
+                "seed {} frame {} tick {}+{}",
+                scene.seed,
+                index,
+                scene.tick,
+                index / sequence.frames_per_tick.max(1)
+            )),
+            _ => lines.push(format!("seed {} tick {}", scene.seed, scene.tick)),
+        }
+        for (key, value) in scene.parameters {
+            let value = match value {
+                Value::String(value) => value,
+                value => value.to_string(),
+            };
+            lines.push(format!("{} {}", key, value));
+        }
+    }
+    lines
+}
+
+pub fn contact_sheet(args: &SheetArgs) -> Result<PathBuf, String> {
+    let frames = frames(&args.dir);
+    if frames.is_empty() {
+        return Err(format!("no frames in {:?}", args.dir));
+    }
+    let width = args.width.max(16);
+
+    let mut cells = Vec::new();
+    for frame in &frames {
+        let image = match image::open(frame) {
+            Ok(image) => image.to_rgba8(),
+            Err(err) => {
+                eprintln!("skipping {:?}: {}", frame, err);
+                continue;
+            }
+        };
+        let height = (width * image.height() / image.width().max(1)).max(1);
+        let thumbnail = imageops::resize(&image, width, height, FilterType::Triangle);
+        cells.push((thumbnail, label(frame)));
+    }
+    if cells.is_empty() {
+        return Err(format!("no readable frames in {:?}", args.dir));
+    }
+
+    let thumbnail_height = cells
+        .iter()
+        .map(|(image, _)| image.height())
+        .max()
+        .unwrap_or(0);
+    let lines = cells
+        .iter()
+        .map(|(_, lines)| lines.len())
+        .max()
+        .unwrap_or(0) as u32;
+    let cell_width = width + PADDING;
+    let cell_height = thumbnail_height + PADDING + lines * LINE_HEIGHT + PADDING;
+    let columns = args.columns.clamp(1, cells.len() as u32);
+    let rows = (cells.len() as u32).div_ceil(columns);
+
+    let mut sheet = RgbaImage::from_pixel(
+        columns * cell_width + PADDING,
+        rows * cell_height + PADDING,
+        BACKGROUND,
+    );
+    for (index, (thumbnail, lines)) in cells.iter().enumerate() {
+        let x = PADDING + (index as u32 % columns) * cell_width;
+        let y = PADDING + (index as u32 / columns) * cell_height;
+        imageops::overlay(&mut sheet, thumbnail, x as i64, y as i64);
+        for (line, text) in lines.iter().enumerate() {
+            let y = y + thumbnail_height + PADDING / 2 + line as u32 * LINE_HEIGHT;
+            draw_text(&mut sheet, x, y, text, width);
+        }
+    }
+
+    let out = args
+        .out
+        .clone()
+        .unwrap_or_else(|| args.dir.join(SHEET_NAME));
+    sheet
+        .save(&out)
+        .map_err(|err| format!("could not write {:?}: {}", out, err))?;
+    Ok(out)
+}
+
+// 3x5 pixel font, a row per byte, the left column in bit 2
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+const FONT_SCALE: u32 = 2;
+const ADVANCE: u32 = (GLYPH_WIDTH + 1) * FONT_SCALE;
+const LINE_HEIGHT: u32 = (GLYPH_HEIGHT + 2) * FONT_SCALE;
+
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_lowercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'a' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'b' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'c' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'd' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'e' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'f' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'g' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'h' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'i' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'j' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'k' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'l' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'm' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'n' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'o' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'p' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'r' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        's' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        't' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'u' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'v' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'w' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'x' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        '[' => [0b110, 0b100, 0b100, 0b100, 0b110],
+        ']' => [0b011, 0b001, 0b001, 0b001, 0b011],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        _ => [0; 5],
+    }
+}
+
+// one line of text, cut off at `max_width`
+fn draw_text(image: &mut RgbaImage, x: u32, y: u32, text: &str, max_width: u32) {
+    for (index, c) in text
+        .chars()
+        .take((max_width / ADVANCE) as usize)
+        .enumerate()
+    {
+        let left = x + index as u32 * ADVANCE;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (0b100 >> column) == 0 {
+                    continue;
+                }
+                for dy in 0..FONT_SCALE {
+                    for dx in 0..FONT_SCALE {
+                        let px = left + column * FONT_SCALE + dx;
+                        let py = y + row as u32 * FONT_SCALE + dy;
+                        if px < image.width() && py < image.height() {
+                            image.put_pixel(px, py, TEXT);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,494 @@
+use std::collections::VecDeque;
+
+use bevy::{ecs::schedule::ScheduleLabel, prelude::*};
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    automaton::GridMasters,
+    grid::{Clock, GridMaster},
+    heightmap::HeightFields,
+    mask::Masks,
+    region::Region,
+    spawn_block::spawn_seed_blocks,
+    AutoCube, ChunkState, ChunkStates,
+};
+// every chunk steps on its own clock
+// -- a chunk's clock runs at its interval times the global speed
+// -- pausing the simulation stops every clock, `steps` then moves
+//    every chunk forward one step per frame until it runs out
+// -- `advance` pushes the clocks forward by a fixed amount of virtual
+//    time while paused, recording uses it so every run plays out the same
+// -- `tick` counts the steps since everything was last reset, with
+//    `until` set the clocks stop moving once it gets there, so a scene
+//    grown by `advance` ends on the same step it was captured at
+// -- all randomness of the simulation comes from SimRng, resetting
+//    everything reseeds it so a seed always grows the same composition
+// -- the clocks move in fixed increments of FIXED_STEP, frame time is
+//    saved up until it makes a whole increment, the chunks whose clock ran
+//    out in an increment step together, in chunk order
+// -- run_steps runs the SimulationStep schedule (update_block and
+//    update_automaton) once per step, so a long `advance` grows as far as the
+//    same time played in real time, and steps draw from SimRng in the same
+//    order whatever the frame rate, many chunks included
+// -- update_block and update_automaton check `stepping` to know if it's their
+//    turn in the current step, commands are applied between steps
+// -- in real time a slow frame catches up at most MAX_INCREMENTS increments,
+//    the rest of the time is dropped, which delays every chunk alike
+// -- a chunk that met its stop condition or used its spawn budget is settled
+//    and doesn't step anymore, so the composition stays put for rendering,
+//    the fill ratio is measured once a frame so a chunk stopping on it may
+//    take a few more steps on a slow frame than on a fast one
+
+// virtual seconds the clocks move at a time
+const FIXED_STEP: f32 = 1. / 120.;
+
+// increments caught up in one real time frame
+const MAX_INCREMENTS: u32 = 30;
+
+// update_block and update_automaton, run once per step by run_steps
+#[derive(ScheduleLabel, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SimulationStep;
+
+// resolution of the occupancy grid the fill ratio is measured on
+const FILL_RESOLUTION: f32 = 32.;
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum StopCondition {
+    Never,
+    // fraction of the bounds grid covered by cubes
+    FillRatio(f32),
+    // seconds of (speed scaled) simulation time
+    TimeLimit(f32),
+}
+
+#[derive(Clone, Default)]
+pub struct Population {
+    pub live: usize,
+    pub spawned: usize,
+    pub elapsed: f32,
+    // only measured for chunks that stop on it
+    pub fill: Option<f32>,
+    pub settled: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Reset {
+    All,
+    Chunk(usize),
+}
+
+#[derive(Resource)]
+pub struct SimRng {
+    pub seed: u64,
+    rng: StdRng,
+}
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        SimRng {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn reseed(&mut self, seed: u64) {
+        *self = SimRng::new(seed);
+    }
+
+    pub fn rng(&mut self) -> &mut StdRng {
+        &mut self.rng
+    }
+}
+
+#[derive(Resource)]
+pub struct Simulation {
+    pub paused: bool,
+    pub speed: f32,
+    pub steps: u32,
+    // virtual seconds to run the clocks for on the next frame while paused
+    pub advance: f32,
+    pub tick: u64,
+    pub until: Option<u64>,
+    pub reset: Option<Reset>,
+    clocks: Vec<Clock>,
+    stepping: Vec<bool>,
+    // virtual seconds saved up towards the next increment
+    pending: f32,
+    // the chunks stepping in each of the steps left this frame
+    steps_left: VecDeque<Vec<bool>>,
+    populations: Vec<Population>,
+    insides: Vec<Option<InsideCells>>,
+}
+
+// the cells of a chunk's fill grid whose centre is in its region, worked
+// out again only when the region or the scale changes
+pub struct InsideCells {
+    region: Region,
+    scale: f32,
+    cells: Vec<bool>,
+    total: usize,
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Simulation {
+            paused: false,
+            speed: 1.0,
+            steps: 0,
+            advance: 0.0,
+            tick: 0,
+            until: None,
+            reset: None,
+            clocks: Vec::new(),
+            stepping: Vec::new(),
+            pending: 0.0,
+            steps_left: VecDeque::new(),
+            populations: Vec::new(),
+            insides: Vec::new(),
+        }
+    }
+}
+
+impl Simulation {
+    pub fn stepping(&self, index: usize) -> bool {
+        self.stepping.get(index).copied().unwrap_or(false)
+    }
+
+    // seconds the clocks move this frame, real time scaled by speed or
+    // the virtual time asked for while paused
+    fn dt(&self, time: &Time) -> f32 {
+        match self.paused {
+            true => self.advance,
+            false => time.delta_seconds() * self.speed,
+        }
+    }
+
+    // sets up the next step, chunks that used their spawn budget in an
+    // earlier step of the frame sit it out, true while steps are left after it
+    fn next_step(&mut self, max_spawns: &[Option<usize>]) -> bool {
+        let stepping = self.steps_left.pop_front().unwrap_or_default();
+        self.stepping.fill(false);
+        for (index, stepping) in stepping.into_iter().enumerate() {
+            let Some(population) = self.populations.get_mut(index) else {
+                continue;
+            };
+            let out_of_spawns = max_spawns
+                .get(index)
+                .copied()
+                .flatten()
+                .is_some_and(|max| population.spawned >= max);
+            population.settled = population.settled || out_of_spawns;
+            self.stepping[index] = stepping && !population.settled;
+        }
+        !self.steps_left.is_empty()
+    }
+
+    pub fn population(&self, index: usize) -> Population {
+        self.populations.get(index).cloned().unwrap_or_default()
+    }
+
+    // how many more cubes the chunk may spawn right now
+    pub fn budget(&self, index: usize, chunk: &ChunkState) -> usize {
+        let population = self.population(index);
+        let live = chunk
+            .max_live
+            .map_or(usize::MAX, |max| max.saturating_sub(population.live));
+        let spawns = chunk
+            .max_spawns
+            .map_or(usize::MAX, |max| max.saturating_sub(population.spawned));
+        live.min(spawns)
+    }
+
+    // back to how the chunk started, clock and counts included
+    pub fn restart(&mut self, index: usize) {
+        if let Some(clock) = self.clocks.get_mut(index) {
+            clock.reset();
+        }
+        for step in self.steps_left.iter_mut() {
+            if let Some(stepping) = step.get_mut(index) {
+                *stepping = false;
+            }
+        }
+        if let Some(population) = self.populations.get_mut(index) {
+            *population = Population::default();
+        }
+    }
+
+    pub fn spawned(&mut self, index: usize, count: usize) {
+        if let Some(population) = self.populations.get_mut(index) {
+            population.live += count;
+            population.spawned += count;
+        }
+    }
+
+    pub fn despawned(&mut self, index: usize) {
+        if let Some(population) = self.populations.get_mut(index) {
+            population.live = population.live.saturating_sub(1);
+        }
+    }
+}
+
+// despawns a chunk's cubes and spawns its seed blocks again
+// automaton chunks get a freshly seeded grid
+pub fn reset_chunks(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    chunk_states: Res<ChunkStates>,
+    mut simulation: ResMut<Simulation>,
+    mut masters: ResMut<GridMasters>,
+    mut sim_rng: ResMut<SimRng>,
+    mut masks: ResMut<Masks>,
+    mut fields: ResMut<HeightFields>,
+    blocks: Query<(Entity, &AutoCube)>,
+) {
+    let Some(reset) = simulation.reset.take() else {
+        return;
+    };
+
+    if reset == Reset::All {
+        let seed = sim_rng.seed;
+        sim_rng.reseed(seed);
+        simulation.tick = 0;
+        simulation.pending = 0.0;
+        simulation.steps_left.clear();
+    }
+
+    for (index, chunk) in chunk_states.0.iter().enumerate() {
+        if reset != Reset::All && reset != Reset::Chunk(index) {
+            continue;
+        }
+        for (entity, block) in blocks.iter() {
+            if block.index == index {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+        if let Some(master) = masters.0.get_mut(index) {
+            *master = None;
+        }
+        if let Some(field) = fields.0.get_mut(index) {
+            *field = None;
+        }
+        simulation.restart(index);
+        spawn_seed_blocks(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            sim_rng.rng(),
+            &mut masks,
+            chunk,
+            index,
+        );
+    }
+}
+
+pub fn count_population(
+    chunk_states: Res<ChunkStates>,
+    mut simulation: ResMut<Simulation>,
+    blocks: Query<(&AutoCube, &Transform)>,
+) {
+    let simulation = simulation.as_mut();
+    let chunks = &chunk_states.0;
+    simulation
+        .populations
+        .resize_with(chunks.len(), Default::default);
+    simulation.insides.resize_with(chunks.len(), || None);
+
+    let mut cubes = vec![Vec::new(); chunks.len()];
+    for (block, transform) in blocks.iter() {
+        if let Some(cubes) = cubes.get_mut(block.index) {
+            cubes.push((transform.translation, block.size));
+        }
+    }
+
+    for (index, chunk) in chunks.iter().enumerate() {
+        let population = &mut simulation.populations[index];
+        population.live = cubes[index].len();
+        population.fill = match chunk.stop {
+            StopCondition::FillRatio(_) => Some(fill_ratio(
+                chunk,
+                &cubes[index],
+                &mut simulation.insides[index],
+            )),
+            _ => None,
+        };
+
+        let stopped = match chunk.stop {
+            StopCondition::Never => false,
+            StopCondition::FillRatio(ratio) => population.fill.is_some_and(|fill| fill >= ratio),
+            StopCondition::TimeLimit(limit) => population.elapsed >= limit,
+        };
+        let out_of_spawns = chunk
+            .max_spawns
+            .is_some_and(|max| population.spawned >= max);
+        population.settled = population.settled || stopped || out_of_spawns;
+    }
+}
+
+// rasterise the cubes onto a grid over the chunk region and
+// count the covered cells, flat axes of the region get a single cell
+// -- with a region only the cells whose centre is inside it count, so a
+//    sphere can fill up as much as a box
+fn fill_ratio(chunk: &ChunkState, cubes: &[(Vec3, f32)], inside: &mut Option<InsideCells>) -> f32 {
+    let (min, max) = chunk.region().aabb();
+    let (min, max) = (min * chunk.scale, max * chunk.scale);
+    let extent = max - min;
+    let cell = extent.max_element() / FILL_RESOLUTION;
+    if cell <= 0.0 {
+        return 0.0;
+    }
+    let cells = (extent / cell).ceil().max(Vec3::ONE);
+    let mut grid = GridMaster::new(cells.x as usize, cells.y as usize, cells.z as usize);
+
+    for &(center, size) in cubes {
+        let from = ((center - size / 2. - min) / cell).floor().max(Vec3::ZERO);
+        let to = ((center + size / 2. - min) / cell).ceil().min(cells);
+        for z in from.z as usize..(to.z as usize).max(from.z as usize + 1) {
+            for y in from.y as usize..to.y as usize {
+                for x in from.x as usize..to.x as usize {
+                    grid.set_occupied(x, y, z, true);
+                }
+            }
+        }
+    }
+
+    let Some(region) = &chunk.region else {
+        let total = cells.x * cells.y * cells.z;
+        return grid.occupied().len() as f32 / total;
+    };
+    let (width, height, depth) = (cells.x as usize, cells.y as usize, cells.z as usize);
+    let stale = !inside
+        .as_ref()
+        .is_some_and(|inside| inside.region == *region && inside.scale == chunk.scale);
+    if stale {
+        let mut inside_cells = Vec::with_capacity(width * height * depth);
+        for z in 0..depth {
+            for y in 0..height {
+                for x in 0..width {
+                    let center = min + (Vec3::new(x as f32, y as f32, z as f32) + 0.5) * cell;
+                    inside_cells.push(region.contains(center / chunk.scale));
+                }
+            }
+        }
+        *inside = Some(InsideCells {
+            region: region.clone(),
+            scale: chunk.scale,
+            total: inside_cells.iter().filter(|inside| **inside).count(),
+            cells: inside_cells,
+        });
+    }
+    let Some(inside) = inside.as_ref().filter(|inside| inside.total > 0) else {
+        return 0.0;
+    };
+    let occupied = grid
+        .occupied()
+        .into_iter()
+        .filter(|&(x, y, z)| inside.cells[(z * height + y) * width + x])
+        .count();
+    occupied as f32 / inside.total as f32
+}
+
+pub fn tick_chunks(
+    time: Res<Time>,
+    chunk_states: Res<ChunkStates>,
+    mut simulation: ResMut<Simulation>,
+) {
+    let simulation = simulation.as_mut();
+    let chunks = &chunk_states.0;
+    simulation.clocks.resize_with(chunks.len(), Clock::new);
+    simulation.stepping.resize(chunks.len(), false);
+    simulation.steps_left.clear();
+
+    // a frame of stepping while paused steps everything once
+    let step = simulation.paused && simulation.steps > 0;
+    if step {
+        simulation.steps -= 1;
+    }
+
+    let dt = simulation.dt(&time);
+    let advance = simulation.paused && simulation.advance > 0.0;
+    simulation.advance = 0.0;
+    if simulation.paused && !advance {
+        if step {
+            let stepping: Vec<bool> = chunks
+                .iter()
+                .zip(&simulation.populations)
+                .map(|(chunk, population)| chunk.playing && !population.settled)
+                .collect();
+            if stepping.iter().any(|stepping| *stepping) {
+                simulation.steps_left.push_back(stepping);
+                simulation.tick += 1;
+            }
+        }
+    } else {
+        simulation.pending += dt;
+    }
+
+    let mut increments = 0;
+    while simulation.pending >= FIXED_STEP {
+        if simulation
+            .until
+            .is_some_and(|until| simulation.tick >= until)
+        {
+            simulation.pending = 0.0;
+            break;
+        }
+        if !advance && increments >= MAX_INCREMENTS {
+            simulation.pending = 0.0;
+            break;
+        }
+        simulation.pending -= FIXED_STEP;
+        increments += 1;
+
+        for (index, chunk) in chunks.iter().enumerate() {
+            let population = &mut simulation.populations[index];
+            if !chunk.playing || population.settled {
+                continue;
+            }
+            population.elapsed += FIXED_STEP;
+            if let StopCondition::TimeLimit(limit) = chunk.stop {
+                population.settled = population.elapsed >= limit;
+            }
+            let clock = &mut simulation.clocks[index];
+            clock.set_interval(chunk.interval);
+            clock.tick(FIXED_STEP);
+        }
+        // more than one step when an interval is shorter than an increment
+        loop {
+            let stepping: Vec<bool> = chunks
+                .iter()
+                .enumerate()
+                .map(|(index, chunk)| {
+                    chunk.playing
+                        && !simulation.populations[index].settled
+                        && simulation.clocks[index].finished()
+                })
+                .collect();
+            if !stepping.iter().any(|stepping| *stepping) {
+                break;
+            }
+            simulation.steps_left.push_back(stepping);
+            simulation.tick += 1;
+        }
+    }
+}
+
+// runs the steps of the frame, the schedule runs at least once a frame so
+// update_automaton can set up chunks that just switched to automaton mode
+pub fn run_steps(world: &mut World) {
+    let max_spawns: Vec<Option<usize>> = world
+        .resource::<ChunkStates>()
+        .0
+        .iter()
+        .map(|chunk| chunk.max_spawns)
+        .collect();
+    loop {
+        let more = world.resource_mut::<Simulation>().next_step(&max_spawns);
+        world.run_schedule(SimulationStep);
+        if !more {
+            break;
+        }
+    }
+    world.resource_mut::<Simulation>().stepping.fill(false);
+}
@@ -1,74 +1,120 @@
 use bevy::{ecs::system::Command, prelude::*};
-use rand::Rng;
+use rand::{Rng, RngCore};
 
-use crate::{make_outline_block, AutoCube, ChunkState, ChunkStates, ColorChannels, Rect, SCALE};
+use crate::{
+    make_outline_block,
+    mask::{cube_color, MaskImage, Masks},
+    outline::Outline,
+    simulation::SimRng,
+    AutoCube, ChunkState, ChunkStates, ColorChannels, Rect, SCALE,
+};
 
 fn _spawn_block(
     commands: &mut Commands,
     meshes: &mut ResMut<Assets<Mesh>>,
     materials: &mut ResMut<Assets<StandardMaterial>>,
+    rng: &mut dyn RngCore,
     chunk: &ChunkState,
     i: usize,
+    mask: Option<&MaskImage>,
 ) {
+    let size = rng.gen_range(0.01..0.5);
+    // masked chunks start inside their mask, chunks with a region inside it
+    let translation = match (&chunk.mask, mask, &chunk.region) {
+        (Some(settings), Some(image), _) => image.sample(chunk, settings, rng),
+        (_, _, Some(region)) => region.sample(rng).map(|point| point * chunk.scale),
+        _ => None,
+    }
+    .unwrap_or(Vec3::new(0.0, 10.0, 0.0) * SCALE);
+    let color = cube_color(mask, chunk, translation);
     commands
         .spawn(PbrBundle {
-            mesh: meshes.add(Mesh::from(shape::Cube {
-                size: rand::thread_rng().gen_range(0.01..0.5),
-            })),
+            mesh: meshes.add(Mesh::from(shape::Cube { size })),
             material: materials.add(StandardMaterial {
-                base_color: chunk.base_color,
+                base_color: color.unwrap_or(chunk.base_color),
                 perceptual_roughness: chunk.perceptual_roughness,
                 reflectance: 0.1,
                 ..default()
             }),
-            transform: Transform::from_translation(Vec3::new(0.0, 10.0, 0.0) * SCALE),
+            transform: Transform::from_translation(translation),
             ..default()
         })
         .insert(AutoCube {
             life_time: chunk.life_time,
             index: i,
+            size,
+            color,
         });
 }
 
+// the blocks a chunk starts from, also used when a chunk is reset
+pub fn spawn_seed_blocks(
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    rng: &mut dyn RngCore,
+    masks: &mut Masks,
+    chunk: &ChunkState,
+    index: usize,
+) {
+    // heightmap fields are built whole, nothing grows from seeds
+    if chunk.heightmap.is_some() {
+        return;
+    }
+    let mask = chunk.mask.as_ref().and_then(|mask| masks.get(mask));
+    for _ in 0..4 {
+        _spawn_block(commands, meshes, materials, rng, chunk, index, mask);
+    }
+}
+
 pub fn init_blocks(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     chunk_states: Res<ChunkStates>,
+    mut sim_rng: ResMut<SimRng>,
+    mut masks: ResMut<Masks>,
 ) {
     let chunks = &chunk_states.0.clone();
-    for _ in 0..4 {
-        let mut index = 0;
-        for chunk in chunks.iter() {
-            _spawn_block(&mut commands, &mut meshes, &mut materials, chunk, index);
-            index += 1;
-        }
+    let mut index = 0;
+    for chunk in chunks.iter() {
+        spawn_seed_blocks(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            sim_rng.rng(),
+            &mut masks,
+            chunk,
+            index,
+        );
+        index += 1;
     }
 
     let mut b = chunks[0].bounds.clone();
     b.min.z = -0.3;
     b.max.z = 0.3;
 
-    // let vec = make_outline_block(&b);
+    // let mesh = make_outline_block(&b, chunks[0].scale, 2., Join::Mitre, Color::BLACK);
 
-    // spawn_from_mesh(&mut commands, vec, &mut meshes, &mut materials);
+    // spawn_from_mesh(&mut commands, mesh, &mut meshes, &mut materials);
 }
 
 fn spawn_from_mesh(
     commands: &mut Commands,
-    mesh_vec: Vec<Mesh>,
+    mesh: Mesh,
     meshes: &mut ResMut<Assets<Mesh>>,
     materials: &mut ResMut<Assets<StandardMaterial>>,
 ) {
-    for mesh in mesh_vec {
-        commands.spawn(PbrBundle {
+    commands.spawn((
+        PbrBundle {
             mesh: meshes.add(mesh),
+            // the stroke color is in the vertex colors
             material: materials.add(StandardMaterial {
-                base_color: Color::rgb(0.0, 0.0, 0.0),
+                base_color: Color::WHITE,
                 ..default()
             }),
-            transform: Transform::from_translation(Vec3::new(0.0, 0.0, 0.0) * SCALE),
             ..default()
-        });
-    }
+        },
+        Outline,
+    ));
 }
@@ -0,0 +1,166 @@
+use std::{fmt::Write, fs, path::Path};
+
+use bevy::prelude::*;
+
+use crate::{
+    export::{capture_name, ExportCamera, ExportSettings},
+    ortho::{collect_cubes, hex, CubeGeometry, Projector},
+    simulation::SimRng,
+    AutoCube,
+};
+// vector export of the orthographic view
+// -- every face of every cube that looks at the camera becomes a polygon
+// -- cubes are painted back to front, each with its front faces, so a
+//    cube's faces never get split up by another's
+// -- the order goes by each cube's nearest corner, then its furthest, so a
+//    small cube in front of a big one is painted after it whatever their
+//    centres, cubes whose depth ranges overlap can still come out in the
+//    wrong order, cubes cutting into each other always do, the lines
+//    export finds the exact visible edges and seams
+// -- the canvas has the export size so it lines up with the raster exports
+
+pub const SVG_DIR: &str = "out/svg";
+
+#[derive(Resource)]
+pub struct SvgExport {
+    pub outline: bool,
+    pub stroke_width: f32,
+    pub stroke_color: Color,
+    // darken faces that turn away from the light a bit
+    pub shading: bool,
+    pub requested: bool,
+}
+
+impl Default for SvgExport {
+    fn default() -> Self {
+        SvgExport {
+            outline: true,
+            stroke_width: 1.,
+            stroke_color: Color::BLACK,
+            shading: true,
+            requested: false,
+        }
+    }
+}
+
+// same direction as the light spawned in setup
+fn light_direction() -> Vec3 {
+    Vec3::new(50.0, 150.0, 100.0).normalize()
+}
+
+pub fn svg_document(
+    projector: &Projector,
+    cubes: &[CubeGeometry],
+    background: Color,
+    settings: &SvgExport,
+) -> String {
+    // (nearest, furthest) corner depth
+    let mut cubes: Vec<((f32, f32), &CubeGeometry)> = cubes
+        .iter()
+        .map(|cube| {
+            let depths = cube.corners.map(|corner| projector.project(corner).1);
+            let near = depths.into_iter().fold(f32::MAX, f32::min);
+            let far = depths.into_iter().fold(f32::MIN, f32::max);
+            ((near, far), cube)
+        })
+        .collect();
+    // furthest first
+    cubes.sort_by(|(a, _), (b, _)| b.0.total_cmp(&a.0).then(b.1.total_cmp(&a.1)));
+
+    let mut polygons = Vec::new();
+    for (_, cube) in cubes {
+        for face in cube.faces() {
+            if !projector.faces_camera(face.normal) {
+                continue;
+            }
+            let projected = face.corners.map(|corner| projector.project(corner));
+            let color = match settings.shading {
+                true => {
+                    let light = 0.6 + 0.4 * face.normal.dot(light_direction()).max(0.0);
+                    let [r, g, b, a] = cube.color.as_rgba_f32();
+                    Color::rgba(r * light, g * light, b * light, a)
+                }
+                false => cube.color,
+            };
+            polygons.push((projected.map(|(point, _)| point), color));
+        }
+    }
+
+    let size = projector.size;
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+        w = size.x,
+        h = size.y
+    );
+    let _ = writeln!(
+        svg,
+        r#"<rect width="100%" height="100%" fill="{}"/>"#,
+        hex(background)
+    );
+
+    let stroke = match settings.outline {
+        true => format!(
+            r#" stroke="{}" stroke-width="{}" stroke-linejoin="round""#,
+            hex(settings.stroke_color),
+            settings.stroke_width
+        ),
+        false => String::new(),
+    };
+    for (points, color) in polygons {
+        let points = points
+            .iter()
+            .map(|point| format!("{:.2},{:.2}", point.x, point.y))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let _ = writeln!(
+            svg,
+            r#"<polygon points="{}" fill="{}" fill-opacity="{}"{}/>"#,
+            points,
+            hex(color),
+            color.a(),
+            stroke
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+pub fn export_svg(
+    mut svg: ResMut<SvgExport>,
+    export_settings: Res<ExportSettings>,
+    sim_rng: Res<SimRng>,
+    clear_color: Res<ClearColor>,
+    materials: Res<Assets<StandardMaterial>>,
+    camera: Query<(&GlobalTransform, &Projection), With<ExportCamera>>,
+    cubes: Query<(&AutoCube, &GlobalTransform, &Handle<StandardMaterial>)>,
+) {
+    if !svg.requested {
+        return;
+    }
+    svg.requested = false;
+
+    let Ok((transform, projection)) = camera.get_single() else {
+        return;
+    };
+    let size = Vec2::new(export_settings.width as f32, export_settings.height as f32);
+    let Some(projector) = Projector::new(transform, projection, size) else {
+        warn!("svg export needs an orthographic camera");
+        return;
+    };
+
+    let document = svg_document(
+        &projector,
+        &collect_cubes(&cubes, &materials),
+        clear_color.0,
+        &svg,
+    );
+    let file = Path::new(SVG_DIR)
+        .join(capture_name(sim_rng.seed))
+        .with_extension("svg");
+    match fs::create_dir_all(SVG_DIR).and_then(|_| fs::write(&file, document)) {
+        Ok(_) => println!("exported {:?}", file),
+        Err(err) => warn!("could not write {:?}: {}", file, err),
+    }
+}
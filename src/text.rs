@@ -0,0 +1,276 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use ab_glyph::{point, Font, FontVec, PxScale, ScaleFont};
+use bevy::prelude::Vec3;
+use image::{Rgba, RgbaImage};
+
+use crate::{
+    headless::parse_option,
+    mask::{Mask, MASKS_DIR},
+    scene::Scene,
+    Bounds, ChunkState,
+};
+// type as growth regions, one chunk per glyph or per word
+//   design_to_blocks text <text> <font.ttf> [--per glyph|word] [--size UNITS]
+//       [--spacing EM] [--line-height EM] [--depth UNITS] [--scene FILE] [--out FILE]
+// -- the text is set with the font's advances and kerning, `spacing` adds
+//    tracking between letters, `\n` starts a new line `line_height` ems lower
+// -- every glyph or word is rasterised into a mask image in
+//    assets/text/<name>/, its chunk gets bounds around it and the mask, so
+//    growth stays inside the letterforms like any other masked chunk
+// -- `size` is the em in bounds units, the first line sits on top at y 0
+//    and the text runs down from there
+// -- the other chunk settings come from the first chunk of the base scene
+
+// pixels per em of the mask images
+const RASTER: f32 = 256.;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Per {
+    Glyph,
+    Word,
+}
+
+#[derive(Clone, Debug)]
+pub struct TextArgs {
+    pub text: String,
+    pub font: PathBuf,
+    pub per: Per,
+    // the em in bounds units
+    pub size: f32,
+    // extra space between letters in ems
+    pub spacing: f32,
+    pub line_height: f32,
+    // thickness of the letters in bounds units
+    pub depth: f32,
+    pub scene: Option<PathBuf>,
+    // text.scene.json when not given
+    pub out: Option<PathBuf>,
+}
+
+impl TextArgs {
+    // the arguments after `text`
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<TextArgs, String> {
+        let words = args.next().ok_or("missing text")?;
+        let font = args.next().ok_or("missing font file")?;
+        let mut text = TextArgs {
+            // the shell hands over \n as two characters
+            text: words.replace("\\n", "\n"),
+            font: font.into(),
+            per: Per::Glyph,
+            size: 2.,
+            spacing: 0.,
+            line_height: 1.2,
+            depth: 0.3,
+            scene: None,
+            out: None,
+        };
+        while let Some(flag) = args.next() {
+            let value = args.next().ok_or(format!("missing value for {}", flag))?;
+            match flag.as_str() {
+                "--per" => {
+                    text.per = match value.as_str() {
+                        "glyph" => Per::Glyph,
+                        "word" => Per::Word,
+                        _ => return Err(format!("invalid value {} for {}", value, flag)),
+                    }
+                }
+                "--size" => text.size = parse_option(&flag, &value)?,
+                "--spacing" => text.spacing = parse_option(&flag, &value)?,
+                "--line-height" => text.line_height = parse_option(&flag, &value)?,
+                "--depth" => text.depth = parse_option(&flag, &value)?,
+                "--scene" => text.scene = Some(value.into()),
+                "--out" => text.out = Some(value.into()),
+                _ => return Err(format!("unknown option {}", flag)),
+            }
+        }
+        Ok(text)
+    }
+}
+
+// a rasterised glyph, in pixels from the top left of the text, y down
+struct Raster {
+    min: (i32, i32),
+    width: u32,
+    height: u32,
+    coverage: Vec<f32>,
+}
+
+// the glyphs of the text with the group (glyph or word) each belongs to
+fn set_text(font: &FontVec, args: &TextArgs) -> Vec<(usize, Raster)> {
+    let scaled = font.as_scaled(PxScale::from(RASTER));
+    let mut glyphs = Vec::new();
+    let mut group = 0;
+    for (line, text) in args.text.lines().enumerate() {
+        let baseline = scaled.ascent() + line as f32 * args.line_height * RASTER;
+        let mut caret = 0.;
+        let mut previous = None;
+        let mut in_word = false;
+        for c in text.chars() {
+            let id = scaled.glyph_id(c);
+            if let Some(previous) = previous {
+                caret += scaled.kern(previous, id) + args.spacing * RASTER;
+            }
+            previous = Some(id);
+
+            let glyph = id.with_scale_and_position(RASTER, point(caret, baseline));
+            caret += scaled.h_advance(id);
+            if c.is_whitespace() {
+                if in_word {
+                    group += 1;
+                }
+                in_word = false;
+                continue;
+            }
+            let Some(outlined) = font.outline_glyph(glyph) else {
+                continue;
+            };
+            let bounds = outlined.px_bounds();
+            let (width, height) = (bounds.width() as u32, bounds.height() as u32);
+            let mut coverage = vec![0.; (width * height) as usize];
+            outlined.draw(|x, y, c| {
+                if x < width && y < height {
+                    coverage[(y * width + x) as usize] = c;
+                }
+            });
+            if in_word && args.per == Per::Glyph {
+                group += 1;
+            }
+            in_word = true;
+            glyphs.push((
+                group,
+                Raster {
+                    min: (bounds.min.x as i32, bounds.min.y as i32),
+                    width,
+                    height,
+                    coverage,
+                },
+            ));
+        }
+        if in_word {
+            group += 1;
+        }
+    }
+    glyphs
+}
+
+// the glyphs of a group on one image, white with the coverage as alpha,
+// and the pixel position of its top left corner
+fn merge(glyphs: &[&Raster]) -> ((i32, i32), RgbaImage) {
+    let min_x = glyphs.iter().map(|g| g.min.0).min().unwrap_or(0);
+    let min_y = glyphs.iter().map(|g| g.min.1).min().unwrap_or(0);
+    let max_x = glyphs
+        .iter()
+        .map(|g| g.min.0 + g.width as i32)
+        .max()
+        .unwrap_or(0);
+    let max_y = glyphs
+        .iter()
+        .map(|g| g.min.1 + g.height as i32)
+        .max()
+        .unwrap_or(0);
+    let mut image = RgbaImage::from_pixel(
+        (max_x - min_x).max(1) as u32,
+        (max_y - min_y).max(1) as u32,
+        Rgba([255, 255, 255, 0]),
+    );
+    for glyph in glyphs {
+        for y in 0..glyph.height {
+            for x in 0..glyph.width {
+                let c = glyph.coverage[(y * glyph.width + x) as usize];
+                let px = (glyph.min.0 - min_x) as u32 + x;
+                let py = (glyph.min.1 - min_y) as u32 + y;
+                let pixel = image.get_pixel_mut(px, py);
+                pixel.0[3] = pixel.0[3].max((c.clamp(0., 1.) * 255.).round() as u8);
+            }
+        }
+    }
+    ((min_x, min_y), image)
+}
+
+// folder name for the masks of a text
+fn slug(text: &str) -> String {
+    let slug: String = text
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c.to_ascii_lowercase(),
+            false => '_',
+        })
+        .take(32)
+        .collect();
+    match slug.trim_matches('_') {
+        "" => "text".into(),
+        slug => slug.into(),
+    }
+}
+
+pub fn text_to_blocks(args: &TextArgs) -> Result<PathBuf, String> {
+    let data =
+        fs::read(&args.font).map_err(|err| format!("could not read {:?}: {}", args.font, err))?;
+    let font = FontVec::try_from_vec(data)
+        .map_err(|err| format!("could not load {:?}: {}", args.font, err))?;
+    let glyphs = set_text(&font, args);
+    if glyphs.is_empty() {
+        return Err("no glyphs to set".into());
+    }
+
+    let mut scene = Scene::base(args.scene.as_deref())?;
+    let template = scene.chunks.first().cloned().unwrap_or_default();
+    let folder = Path::new("text").join(slug(&args.text));
+    fs::create_dir_all(Path::new(MASKS_DIR).join(&folder))
+        .map_err(|err| format!("could not create {:?}: {}", folder, err))?;
+
+    let groups = glyphs.iter().map(|(group, _)| *group).max().unwrap_or(0) + 1;
+    let to_units = args.size / RASTER;
+    let mut chunks = Vec::new();
+    for group in 0..groups {
+        let members: Vec<&Raster> = glyphs
+            .iter()
+            .filter(|(g, _)| *g == group)
+            .map(|(_, raster)| raster)
+            .collect();
+        if members.is_empty() {
+            continue;
+        }
+        let ((x, y), image) = merge(&members);
+        let file = folder.join(format!("{:03}.png", chunks.len()));
+        image
+            .save(Path::new(MASKS_DIR).join(&file))
+            .map_err(|err| format!("could not write {:?}: {}", file, err))?;
+
+        let mut chunk: ChunkState = template.clone();
+        // y down in pixels, y up in the scene
+        chunk.bounds = Bounds {
+            min: Vec3::new(
+                x as f32 * to_units,
+                -((y + image.height() as i32) as f32) * to_units,
+                0.,
+            ),
+            max: Vec3::new(
+                (x + image.width() as i32) as f32 * to_units,
+                -(y as f32) * to_units,
+                args.depth,
+            ),
+        };
+        chunk.region = None;
+        chunk.mask = Some(Mask {
+            image: file.to_string_lossy().replace('\\', "/"),
+            ..Mask::default()
+        });
+        chunks.push(chunk);
+    }
+    scene.chunks = chunks;
+
+    let out = args
+        .out
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("text.scene.json"));
+    scene
+        .write(&out)
+        .map_err(|err| format!("could not write {:?}: {}", out, err))?;
+    println!("{} chunks, masks in {:?}", scene.chunks.len(), folder);
+    Ok(out)
+}
@@ -1,13 +1,28 @@
-use bevy::prelude::*;
+use bevy::{ecs::system::SystemParam, prelude::*};
 use bevy_egui::{
     egui::{self, Context, InnerResponse, Ui, Widget},
     EguiContexts,
 };
-use bevy_image_export::ImageExportSettings;
 
 use crate::{
+    automaton::{Automaton, Dimensions, LifeRule},
+    bounds::BoundsGizmo,
+    export::{Capture, ExportSettings, MAX_EXPORT_SIZE, PRINT_SIZES},
+    geometry::{GeometryExport, GeometryFormat},
+    growth::Growth,
+    heightmap::{HeightMode, Heightmap},
+    lines::{LineExport, LineFormat},
+    mask::Mask,
     modes::{CameraModes, CameraSelection, EditBlockModes, Modes},
+    outline::{Join, OutlineStyle},
+    passes::{Pass, Passes},
+    plotter::{PlotterExport, PlotterFormat},
+    record::{CameraMotion, Recording},
+    region::Region,
+    scene::CameraState,
     setup::PlisCamera,
+    simulation::{Reset, SimRng, Simulation, StopCondition},
+    svg::SvgExport,
     Bounds, ChunkStates, ColorChannels, UIState,
 };
 
@@ -66,6 +81,10 @@ fn if_ui_needed(ctx: &mut Context, variables: &mut ChunkStates, index: usize) {
             egui::Slider::new(&mut variables.0[index].scale, 0.3..=20.0).ui(ui);
             ui.end_row();
 
+            ui.label("Step interval:");
+            egui::Slider::new(&mut variables.0[index].interval, 0.05..=5.0).ui(ui);
+            ui.end_row();
+
             ui.label("Perceptual roughness:");
             egui::Slider::new(&mut variables.0[index].perceptual_roughness, 0.01..=1.0).ui(ui);
             ui.end_row();
@@ -75,6 +94,43 @@ fn if_ui_needed(ctx: &mut Context, variables: &mut ChunkStates, index: usize) {
             ui.selectable_value(&mut variables.0[index].inter_color, ColorChannels::B, "B");
             ui.end_row();
 
+            ui.label("Growth:");
+            growth_edit_widget(ui, &mut variables.0[index].growth);
+            ui.end_row();
+
+            ui.label("Cellular automaton:");
+            automaton_edit_widget(ui, &mut variables.0[index].automaton);
+            ui.end_row();
+
+            ui.label("Max live:");
+            optional_count_widget(ui, &mut variables.0[index].max_live, 400);
+            ui.end_row();
+
+            ui.label("Max spawns:");
+            optional_count_widget(ui, &mut variables.0[index].max_spawns, 2000);
+            ui.end_row();
+
+            ui.label("Stop when:");
+            stop_condition_widget(ui, &mut variables.0[index].stop);
+            ui.end_row();
+
+            ui.label("Cube outlines:");
+            outline_style_widget(ui, &mut variables.0[index].outline);
+            ui.end_row();
+
+            ui.label("Mask image:");
+            mask_widget(ui, &mut variables.0[index].mask);
+            ui.end_row();
+
+            ui.label("Heightmap:");
+            heightmap_widget(ui, &mut variables.0[index].heightmap);
+            ui.end_row();
+
+            ui.label("Region:");
+            let chunk = &mut variables.0[index];
+            region_widget(ui, &mut chunk.region, &chunk.bounds);
+            ui.end_row();
+
             ui.selectable_value(&mut variables.0[index].playing, true, "Playing");
             ui.selectable_value(&mut variables.0[index].playing, false, "Paused");
 
@@ -93,12 +149,345 @@ fn if_ui_needed(ctx: &mut Context, variables: &mut ChunkStates, index: usize) {
     });
 }
 
+fn simulation_ui(
+    ctx: &mut Context,
+    simulation: &mut Simulation,
+    sim_rng: &mut SimRng,
+    chunk_states: &ChunkStates,
+    selected: &mut usize,
+    bounds_gizmo: &mut BoundsGizmo,
+) {
+    egui::Window::new("Simulation").show(ctx, |ui| {
+        egui::Grid::new("simulation").show(ui, |ui| {
+            ui.selectable_value(&mut simulation.paused, false, "Play");
+            ui.selectable_value(&mut simulation.paused, true, "Pause");
+            ui.label(format!("Tick: {}", simulation.tick));
+            ui.end_row();
+
+            ui.label("Speed:");
+            egui::Slider::new(&mut simulation.speed, 0.0..=10.0).ui(ui);
+            ui.end_row();
+
+            ui.add_enabled_ui(simulation.paused, |ui| {
+                if ui.button("Step").clicked() {
+                    simulation.steps += 1;
+                }
+            });
+            ui.add_enabled_ui(simulation.paused, |ui| {
+                if ui.button("Step 10").clicked() {
+                    simulation.steps += 10;
+                }
+            });
+            ui.label(match simulation.steps {
+                0 => String::new(),
+                steps => format!("{} to go", steps),
+            });
+            ui.end_row();
+
+            if ui.button("Reset all").clicked() {
+                simulation.reset = Some(Reset::All);
+            }
+            if ui.button("Reset selected").clicked() {
+                simulation.reset = Some(Reset::Chunk(*selected));
+            }
+            ui.end_row();
+
+            ui.label("Seed:");
+            let mut seed = sim_rng.seed;
+            ui.add(egui::DragValue::new(&mut seed));
+            if ui.button("New seed").clicked() {
+                seed = rand::random();
+            }
+            if seed != sim_rng.seed {
+                // a new seed only means something from the start
+                sim_rng.reseed(seed);
+                simulation.reset = Some(Reset::All);
+            }
+            ui.end_row();
+
+            ui.label("Bounds:");
+            ui.checkbox(&mut bounds_gizmo.visible, "Show (B)");
+            ui.end_row();
+        });
+
+        ui.separator();
+        egui::Grid::new("population").striped(true).show(ui, |ui| {
+            ui.label("Chunk");
+            ui.label("Live");
+            ui.label("Spawned");
+            ui.label("Fill");
+            ui.label("Time");
+            ui.label("");
+            ui.end_row();
+
+            for index in 0..chunk_states.0.len() {
+                let population = simulation.population(index);
+                ui.selectable_value(selected, index, format!("{}", index));
+                ui.label(format!("{}", population.live));
+                ui.label(format!("{}", population.spawned));
+                ui.label(match population.fill {
+                    Some(fill) => format!("{:.0}%", fill * 100.),
+                    None => "-".into(),
+                });
+                ui.label(format!("{:.1}s", population.elapsed));
+                ui.label(match population.settled {
+                    true => "settled",
+                    false => "",
+                });
+                ui.end_row();
+            }
+        });
+    });
+}
+
+// everything the export window edits
+#[derive(SystemParam)]
+pub struct Exports<'w, 's> {
+    settings: ResMut<'w, ExportSettings>,
+    // edits a draft, the export texture is only recreated on apply
+    draft: Local<'s, Option<ExportSettings>>,
+    capture: ResMut<'w, Capture>,
+    recording: ResMut<'w, Recording>,
+    svg: ResMut<'w, SvgExport>,
+    lines: ResMut<'w, LineExport>,
+    plotter: ResMut<'w, PlotterExport>,
+    geometry: ResMut<'w, GeometryExport>,
+    passes: ResMut<'w, Passes>,
+}
+
+fn export_ui(ctx: &mut Context, exports: &mut Exports, camera: CameraState, chunks: usize) {
+    let Exports {
+        settings,
+        draft,
+        capture,
+        recording,
+        svg,
+        lines,
+        plotter,
+        geometry,
+        passes,
+    } = exports;
+    let draft = draft.get_or_insert_with(|| settings.as_ref().clone());
+    egui::Window::new("Export").show(ctx, |ui| {
+        egui::Grid::new("export").show(ui, |ui| {
+            ui.label("Width:");
+            ui.add(egui::DragValue::new(&mut draft.width).clamp_range(1..=MAX_EXPORT_SIZE));
+            ui.end_row();
+
+            ui.label("Height:");
+            ui.add(egui::DragValue::new(&mut draft.height).clamp_range(1..=MAX_EXPORT_SIZE));
+            ui.end_row();
+
+            ui.label("DPI:");
+            ui.add(egui::DragValue::new(&mut draft.dpi).clamp_range(1.0..=2400.0));
+            ui.end_row();
+
+            let mm = draft.print_mm();
+            ui.label("Print:");
+            ui.label(format!("{:.0} x {:.0} mm", mm.x, mm.y));
+            ui.end_row();
+        });
+
+        ui.horizontal_wrapped(|ui| {
+            for print in PRINT_SIZES.iter() {
+                if ui.button(print.name).clicked() {
+                    *draft = ExportSettings::from_print(print, draft.dpi, false);
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Swap orientation").clicked() {
+                std::mem::swap(&mut draft.width, &mut draft.height);
+            }
+            if ui.button("Apply").clicked() && **settings != *draft {
+                **settings = draft.clone();
+            }
+        });
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Warmup frames:");
+            ui.add(egui::DragValue::new(&mut capture.warmup).clamp_range(0..=600));
+        });
+        ui.add_enabled_ui(!capture.busy() && !recording.busy(), |ui| {
+            if ui.button("Capture still (S)").clicked() {
+                capture.requested = true;
+            }
+        });
+
+        ui.separator();
+        egui::Grid::new("recording").show(ui, |ui| {
+            ui.label("Ticks:");
+            ui.add(egui::DragValue::new(&mut recording.ticks).clamp_range(1..=100_000));
+            ui.end_row();
+
+            ui.label("Seconds per tick:");
+            ui.add(egui::DragValue::new(&mut recording.tick_seconds).clamp_range(0.01..=10.0));
+            ui.end_row();
+
+            ui.label("Frames per tick:");
+            ui.add(egui::DragValue::new(&mut recording.frames_per_tick).clamp_range(1..=120));
+            ui.end_row();
+
+            ui.label("Settle frames:");
+            ui.add(egui::DragValue::new(&mut recording.settle).clamp_range(1..=120));
+            ui.end_row();
+
+            ui.label("Grow:");
+            ui.checkbox(&mut recording.grow, "");
+            ui.end_row();
+        });
+        camera_motion_widget(ui, &mut recording.camera, camera);
+        match recording.progress() {
+            Some((tick, ticks)) => {
+                ui.label(format!("Recording tick {} / {}", tick, ticks));
+            }
+            None => {
+                ui.add_enabled_ui(!capture.busy(), |ui| {
+                    if ui.button("Record sequence").clicked() {
+                        recording.requested = true;
+                    }
+                });
+            }
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Passes:");
+            for pass in Pass::all() {
+                let mut enabled = passes.enabled.contains(&pass);
+                if ui.checkbox(&mut enabled, pass.name()).changed() {
+                    match enabled {
+                        true => passes.enabled.push(pass),
+                        false => passes.enabled.retain(|p| *p != pass),
+                    }
+                }
+            }
+        });
+
+        ui.separator();
+        egui::Grid::new("svg").show(ui, |ui| {
+            ui.label("Outline:");
+            ui.checkbox(&mut svg.outline, "");
+            ui.end_row();
+
+            ui.label("Stroke width:");
+            ui.add(egui::DragValue::new(&mut svg.stroke_width).clamp_range(0.0..=50.0));
+            ui.end_row();
+
+            ui.label("Stroke color:");
+            color_picker_widget(ui, &mut svg.stroke_color);
+            ui.end_row();
+
+            ui.label("Shading:");
+            ui.checkbox(&mut svg.shading, "");
+            ui.end_row();
+        });
+        if ui.button("Export SVG").clicked() {
+            svg.requested = true;
+        }
+
+        ui.separator();
+        egui::Grid::new("lines").show(ui, |ui| {
+            ui.label("Line format:");
+            egui::ComboBox::from_id_source("line_format")
+                .selected_text(lines.format.name())
+                .show_ui(ui, |ui| {
+                    for format in LineFormat::all() {
+                        ui.selectable_value(&mut lines.format, format, format.name());
+                    }
+                });
+            ui.end_row();
+
+            ui.label("Stroke width:");
+            ui.add(egui::DragValue::new(&mut lines.stroke_width).clamp_range(0.0..=50.0));
+            ui.end_row();
+
+            ui.label("Stroke color:");
+            color_picker_widget(ui, &mut lines.stroke_color);
+            ui.end_row();
+        });
+        if ui.button("Export hidden-line drawing").clicked() {
+            lines.requested = true;
+        }
+
+        ui.separator();
+        egui::Grid::new("plotter").show(ui, |ui| {
+            ui.label("Plotter format:");
+            egui::ComboBox::from_id_source("plotter_format")
+                .selected_text(plotter.format.name())
+                .show_ui(ui, |ui| {
+                    for format in PlotterFormat::all() {
+                        ui.selectable_value(&mut plotter.format, format, format.name());
+                    }
+                });
+            ui.end_row();
+
+            if plotter.pens.len() < chunks {
+                plotter.pens.resize(chunks, 1);
+            }
+            for (index, pen) in plotter.pens.iter_mut().take(chunks).enumerate() {
+                ui.label(format!("Chunk {} pen:", index));
+                ui.add(egui::DragValue::new(pen).clamp_range(1..=8));
+                ui.end_row();
+            }
+
+            if plotter.format == PlotterFormat::Gcode {
+                ui.label("Feed rate (mm/min):");
+                ui.add(egui::DragValue::new(&mut plotter.feed_rate).clamp_range(1.0..=20000.0));
+                ui.end_row();
+
+                ui.label("Pen up Z:");
+                ui.add(egui::DragValue::new(&mut plotter.pen_up_z).speed(0.1));
+                ui.end_row();
+
+                ui.label("Pen down Z:");
+                ui.add(egui::DragValue::new(&mut plotter.pen_down_z).speed(0.1));
+                ui.end_row();
+            }
+        });
+        if ui.button("Export plotter file").clicked() {
+            plotter.requested = true;
+        }
+
+        ui.separator();
+        egui::Grid::new("geometry").show(ui, |ui| {
+            ui.label("Format:");
+            egui::ComboBox::from_id_source("geometry_format")
+                .selected_text(geometry.format.name())
+                .show_ui(ui, |ui| {
+                    for format in GeometryFormat::all() {
+                        ui.selectable_value(&mut geometry.format, format, format.name());
+                    }
+                });
+            ui.end_row();
+
+            ui.label("Outlines:");
+            ui.checkbox(&mut geometry.outlines, "");
+            ui.end_row();
+
+            ui.label("Unit scale:");
+            ui.add(
+                egui::DragValue::new(&mut geometry.unit_scale)
+                    .speed(0.01)
+                    .clamp_range(0.001..=1000.0),
+            );
+            ui.end_row();
+        });
+        if ui.button("Export geometry").clicked() {
+            geometry.requested = true;
+        }
+    });
+}
+
 pub fn update(
     mut contexts: EguiContexts,
     variables: ResMut<ChunkStates>,
     mut state: ResMut<UIState>,
+    mut simulation: ResMut<Simulation>,
+    mut exports: Exports,
+    mut sim_rng: ResMut<SimRng>,
+    mut bounds_gizmo: ResMut<BoundsGizmo>,
 
-    mut render: Query<&mut ImageExportSettings>,
     mut query: Query<(Entity, &mut Projection), With<PlisCamera>>,
     mut transform: Query<&mut Transform, With<PlisCamera>>,
     keycode: Res<Input<KeyCode>>,
@@ -114,20 +503,44 @@ pub fn update(
     let mut transform = transform.single_mut();
     let transform = transform.as_mut();
 
-    let mut render = render.single_mut();
-
     let _ = match state.mode.clone() {
         Modes::Home => {
-            if keycode.just_pressed(KeyCode::C) {
+            state.selected = state.selected.min(chunk_states.0.len().saturating_sub(1));
+            simulation_ui(
+                ctx,
+                &mut simulation,
+                &mut sim_rng,
+                chunk_states,
+                &mut state.selected,
+                &mut bounds_gizmo,
+            );
+            if !chunk_states.0.is_empty() {
+                if_ui_needed(ctx, chunk_states, state.selected);
+            }
+            export_ui(
+                ctx,
+                &mut exports,
+                CameraState::new(transform, projection),
+                chunk_states.0.len(),
+            );
+
+            // letters typed into a text field aren't hotkeys
+            let typing = ctx.wants_keyboard_input();
+            if !typing && keycode.just_pressed(KeyCode::C) {
                 state.mode = Modes::Camera(CameraModes::Selection(CameraSelection));
             }
-            if keycode.pressed(KeyCode::R) {
-                println!("rendering");
-                render.render = true;
+            if !typing
+                && keycode.just_pressed(KeyCode::S)
+                && !exports.capture.busy()
+                && !exports.recording.busy()
+            {
+                exports.capture.requested = true;
+            }
+            if !typing && keycode.just_pressed(KeyCode::Space) {
+                simulation.paused = !simulation.paused;
             }
-            if keycode.just_released(KeyCode::R) {
-                println!("set to no");
-                render.render = false;
+            if !typing && keycode.just_pressed(KeyCode::B) {
+                bounds_gizmo.visible = !bounds_gizmo.visible;
             }
         }
         Modes::Camera(mode) => handle_camera_mode(ctx, state, keycode, mode, transform, projection),
@@ -154,6 +567,333 @@ fn location_edit_widget(location: &mut Vec3) -> impl Widget + '_ {
     }
 }
 
+fn growth_edit_widget(ui: &mut egui::Ui, growth: &mut Growth) {
+    ui.vertical(|ui| {
+        egui::ComboBox::from_id_source("growth")
+            .selected_text(growth.name())
+            .show_ui(ui, |ui| {
+                for rule in Growth::all() {
+                    let name = rule.name();
+                    if ui.selectable_label(growth.name() == name, name).clicked() {
+                        *growth = rule;
+                    }
+                }
+            });
+
+        match growth {
+            Growth::RandomWalk(r) => {
+                ui.add(egui::Slider::new(&mut r.step, 0.0..=2.0).text("Step"));
+            }
+            Growth::Branching(r) => {
+                ui.add(egui::Slider::new(&mut r.children, 1..=6).text("Children"));
+                ui.add(egui::Slider::new(&mut r.step, 0.0..=2.0).text("Step"));
+            }
+            Growth::Drift(r) => {
+                ui.label("Direction:");
+                ui.add(location_edit_widget(&mut r.direction));
+                ui.add(egui::Slider::new(&mut r.strength, 0.0..=2.0).text("Strength"));
+                ui.add(egui::Slider::new(&mut r.step, 0.0..=2.0).text("Step"));
+            }
+            Growth::Attract(r) => {
+                ui.label("Target:");
+                ui.add(location_edit_widget(&mut r.target));
+                ui.add(egui::Slider::new(&mut r.strength, 0.0..=1.0).text("Strength"));
+                ui.add(egui::Slider::new(&mut r.step, 0.0..=2.0).text("Step"));
+            }
+            Growth::Stack(r) => {
+                ui.label("Axis:");
+                ui.add(location_edit_widget(&mut r.axis));
+                ui.add(egui::Slider::new(&mut r.spacing, 0.0..=2.0).text("Spacing"));
+                ui.add(egui::Slider::new(&mut r.jitter, 0.0..=0.5).text("Jitter"));
+            }
+            Growth::Dla(r) => {
+                ui.add(egui::Slider::new(&mut r.walkers, 1..=8).text("Walkers"));
+                ui.add(egui::Slider::new(&mut r.steps, 10..=1000).text("Steps"));
+                ui.add(egui::Slider::new(&mut r.step, 0.0..=2.0).text("Step"));
+                ui.add(egui::Slider::new(&mut r.stick, 0.05..=1.0).text("Stick distance"));
+            }
+        }
+    });
+}
+
+fn automaton_edit_widget(ui: &mut egui::Ui, automaton: &mut Option<Automaton>) {
+    ui.vertical(|ui| {
+        let mut enabled = automaton.is_some();
+        if ui.checkbox(&mut enabled, "Enabled").changed() {
+            *automaton = match enabled {
+                true => Some(Automaton::default()),
+                false => None,
+            };
+        }
+
+        if let Some(automaton) = automaton {
+            ui.horizontal(|ui| {
+                ui.label("Rule:");
+                if ui.text_edit_singleline(&mut automaton.notation).changed() {
+                    if let Some(rule) = LifeRule::parse(&automaton.notation) {
+                        automaton.rule = rule;
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.selectable_value(&mut automaton.dimensions, Dimensions::Two, "2D");
+                ui.selectable_value(&mut automaton.dimensions, Dimensions::Three, "3D");
+            });
+            ui.add(egui::Slider::new(&mut automaton.cell, 0.05..=1.0).text("Cell size"));
+            ui.add(egui::Slider::new(&mut automaton.density, 0.0..=1.0).text("Density"));
+        }
+    });
+}
+
+fn camera_motion_widget(ui: &mut egui::Ui, motion: &mut CameraMotion, camera: CameraState) {
+    ui.horizontal(|ui| {
+        ui.label("Camera:");
+        if ui
+            .selectable_label(*motion == CameraMotion::Still, "Still")
+            .clicked()
+        {
+            *motion = CameraMotion::Still;
+        }
+        if ui
+            .selectable_label(
+                matches!(motion, CameraMotion::Turntable { .. }),
+                "Turntable",
+            )
+            .clicked()
+        {
+            *motion = CameraMotion::Turntable {
+                axis: Vec3::Y,
+                degrees: 360.,
+            };
+        }
+        if ui
+            .selectable_label(matches!(motion, CameraMotion::Path(_)), "Path")
+            .clicked()
+            && !matches!(motion, CameraMotion::Path(_))
+        {
+            *motion = CameraMotion::Path(Vec::new());
+        }
+    });
+
+    match motion {
+        CameraMotion::Still => {}
+        CameraMotion::Turntable { axis, degrees } => {
+            ui.label("Axis:");
+            ui.add(location_edit_widget(axis));
+            ui.add(egui::Slider::new(degrees, -720.0..=720.0).text("Degrees"));
+        }
+        CameraMotion::Path(keys) => {
+            ui.label(format!("{} keys", keys.len()));
+            ui.horizontal(|ui| {
+                if ui.button("Add current camera").clicked() {
+                    keys.push(camera);
+                }
+                if ui.button("Clear").clicked() {
+                    keys.clear();
+                }
+            });
+        }
+    }
+}
+
+fn optional_count_widget(ui: &mut egui::Ui, count: &mut Option<usize>, default: usize) {
+    ui.horizontal(|ui| {
+        let mut enabled = count.is_some();
+        if ui.checkbox(&mut enabled, "").changed() {
+            *count = enabled.then_some(default);
+        }
+        if let Some(count) = count {
+            ui.add(egui::DragValue::new(count).clamp_range(1..=100_000));
+        }
+    });
+}
+
+fn outline_style_widget(ui: &mut egui::Ui, outline: &mut Option<OutlineStyle>) {
+    ui.horizontal(|ui| {
+        let mut enabled = outline.is_some();
+        if ui.checkbox(&mut enabled, "").changed() {
+            *outline = enabled.then(OutlineStyle::default);
+        }
+        if let Some(style) = outline {
+            ui.add(
+                egui::DragValue::new(&mut style.width)
+                    .speed(0.1)
+                    .clamp_range(0.1..=50.0),
+            );
+            for join in Join::all() {
+                ui.selectable_value(&mut style.join, join, join.name());
+            }
+            color_picker_widget(ui, &mut style.color);
+        }
+    });
+}
+
+fn mask_widget(ui: &mut egui::Ui, mask: &mut Option<Mask>) {
+    ui.horizontal(|ui| {
+        let mut enabled = mask.is_some();
+        if ui.checkbox(&mut enabled, "").changed() {
+            *mask = enabled.then(Mask::default);
+        }
+        if let Some(mask) = mask {
+            ui.add(egui::TextEdit::singleline(&mut mask.image).hint_text("logo.png"));
+            ui.add(
+                egui::DragValue::new(&mut mask.threshold)
+                    .speed(0.01)
+                    .clamp_range(0.0..=1.0),
+            );
+            ui.checkbox(&mut mask.color, "Pixel colors");
+        }
+    });
+}
+
+fn heightmap_widget(ui: &mut egui::Ui, heightmap: &mut Option<Heightmap>) {
+    ui.vertical(|ui| {
+        ui.horizontal(|ui| {
+            let mut enabled = heightmap.is_some();
+            if ui.checkbox(&mut enabled, "").changed() {
+                *heightmap = enabled.then(Heightmap::default);
+            }
+            if let Some(heightmap) = heightmap.as_mut() {
+                ui.add(egui::TextEdit::singleline(&mut heightmap.image).hint_text("height.png"));
+            }
+        });
+        if let Some(heightmap) = heightmap {
+            ui.horizontal(|ui| {
+                ui.label("Cell:");
+                ui.add(
+                    egui::DragValue::new(&mut heightmap.cell)
+                        .speed(0.01)
+                        .clamp_range(0.01..=5.0),
+                );
+                ui.label("Levels:");
+                ui.add(egui::DragValue::new(&mut heightmap.levels).clamp_range(1..=64));
+                for mode in HeightMode::all() {
+                    ui.selectable_value(&mut heightmap.mode, mode, mode.name());
+                }
+            });
+        }
+    });
+}
+
+fn vec3_widget(ui: &mut egui::Ui, value: &mut Vec3) {
+    ui.add(egui::DragValue::new(&mut value.x).speed(0.05));
+    ui.add(egui::DragValue::new(&mut value.y).speed(0.05));
+    ui.add(egui::DragValue::new(&mut value.z).speed(0.05));
+}
+
+fn region_widget(ui: &mut egui::Ui, region: &mut Option<Region>, bounds: &Bounds) {
+    ui.vertical(|ui| {
+        ui.horizontal(|ui| {
+            let mut enabled = region.is_some();
+            if ui.checkbox(&mut enabled, "").changed() {
+                *region = enabled.then(|| Region::from(bounds));
+            }
+            if let Some(current) = region.as_mut() {
+                for primitive in Region::primitives(bounds) {
+                    let selected = current.name() == primitive.name();
+                    if ui.selectable_label(selected, primitive.name()).clicked() && !selected {
+                        *current = primitive;
+                    }
+                }
+            }
+        });
+        match region {
+            None => {}
+            Some(Region::Cuboid { min, max }) => {
+                ui.horizontal(|ui| {
+                    ui.label("Min:");
+                    vec3_widget(ui, min);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Max:");
+                    vec3_widget(ui, max);
+                });
+            }
+            Some(Region::Sphere { center, radius }) => {
+                ui.horizontal(|ui| {
+                    ui.label("Center:");
+                    vec3_widget(ui, center);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Radius:");
+                    ui.add(
+                        egui::DragValue::new(radius)
+                            .speed(0.05)
+                            .clamp_range(0.0..=f32::MAX),
+                    );
+                });
+            }
+            Some(Region::Cylinder { base, axis, radius }) => {
+                ui.horizontal(|ui| {
+                    ui.label("Base:");
+                    vec3_widget(ui, base);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Axis:");
+                    vec3_widget(ui, axis);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Radius:");
+                    ui.add(
+                        egui::DragValue::new(radius)
+                            .speed(0.05)
+                            .clamp_range(0.0..=f32::MAX),
+                    );
+                });
+            }
+            Some(Region::Extrusion {
+                points,
+                min_z,
+                max_z,
+            }) => {
+                ui.horizontal(|ui| {
+                    ui.label("Z:");
+                    ui.add(egui::DragValue::new(min_z).speed(0.05));
+                    ui.add(egui::DragValue::new(max_z).speed(0.05));
+                });
+                ui.label(format!("{} points, edited in the scene file", points.len()));
+            }
+            Some(composite) => {
+                ui.label(format!("{}, edited in the scene file", composite.name()));
+            }
+        }
+    });
+}
+
+fn stop_condition_widget(ui: &mut egui::Ui, stop: &mut StopCondition) {
+    ui.vertical(|ui| {
+        ui.horizontal(|ui| {
+            if ui
+                .selectable_label(*stop == StopCondition::Never, "Never")
+                .clicked()
+            {
+                *stop = StopCondition::Never;
+            }
+            if ui
+                .selectable_label(matches!(stop, StopCondition::FillRatio(_)), "Fill ratio")
+                .clicked()
+            {
+                *stop = StopCondition::FillRatio(0.5);
+            }
+            if ui
+                .selectable_label(matches!(stop, StopCondition::TimeLimit(_)), "Time limit")
+                .clicked()
+            {
+                *stop = StopCondition::TimeLimit(60.);
+            }
+        });
+        match stop {
+            StopCondition::Never => {}
+            StopCondition::FillRatio(ratio) => {
+                ui.add(egui::Slider::new(ratio, 0.01..=1.0).text("Fill"));
+            }
+            StopCondition::TimeLimit(limit) => {
+                ui.add(egui::Slider::new(limit, 1.0..=600.0).text("Seconds"));
+            }
+        }
+    });
+}
+
 fn color_picker_widget(ui: &mut egui::Ui, color: &mut Color) -> egui::Response {
     let [r, g, b, a] = color.as_rgba_f32();
     let mut egui_color: egui::Rgba = egui::Rgba::from_srgba_unmultiplied(
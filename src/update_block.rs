@@ -1,11 +1,17 @@
 use bevy::prelude::*;
 use rand::Rng;
 
-use crate::{AutoCube, Bounds, ColorChannels, Temp, LIFETIME, SCALE};
+use crate::{
+    growth::Cluster,
+    mask::{cube_color, Masks, ATTEMPTS},
+    simulation::{SimRng, Simulation},
+    AutoCube, ColorChannels, Temp, LIFETIME, SCALE,
+};
 // a block that will have x lifetime
-// it will spawn a block next to it which will have x life time
+// it will spawn blocks (where is up to the chunk's growth rule) which will have x life time
 // every iteration the blocks that have full life will spawn a new box
 // the blocks that have 0 life will be removed
+// nothing happens between steps, colors included
 
 pub fn update_block(
     mut meshes: ResMut<Assets<Mesh>>,
@@ -13,44 +19,112 @@ pub fn update_block(
     mut commands: Commands,
     mut blocks: Query<(Entity, &mut AutoCube, &Transform, &Handle<StandardMaterial>)>,
     variables: Res<crate::ChunkStates>,
+    mut simulation: ResMut<Simulation>,
+    mut sim_rng: ResMut<SimRng>,
+    mut masks: ResMut<Masks>,
 ) {
+    if !(0..variables.0.len()).any(|index| simulation.stepping(index)) {
+        return;
+    }
+    let rng = sim_rng.rng();
+
+    // max_live and max_spawns, what's left of them this step
+    let mut budgets: Vec<usize> = variables
+        .0
+        .iter()
+        .enumerate()
+        .map(|(index, chunk)| simulation.budget(index, chunk))
+        .collect();
+
+    // every live block of a chunk, the growth rules can look at the whole swarm
+    let mut clusters = vec![Vec::new(); variables.0.len()];
+    for (_, block, transform, _) in blocks.iter() {
+        clusters[block.index].push(transform.translation);
+    }
+    let clusters: Vec<Cluster> = clusters.into_iter().map(Cluster::new).collect();
+
+    // masked chunks whose image can't be read (yet) wait for it, growing
+    // without the mask would put blocks where it says they can't be
+    let waiting: Vec<bool> = variables
+        .0
+        .iter()
+        .map(|chunk| {
+            chunk
+                .mask
+                .as_ref()
+                .is_some_and(|mask| masks.get(mask).is_none())
+        })
+        .collect();
+
     for (entity, mut block, transform, material) in blocks.iter_mut() {
         let variables = variables.0[block.index].clone();
 
-        if variables.playing {
+        // cells of an automaton live and die by update_automaton,
+        // heightmap fields by update_heightmaps
+        if variables.automaton.is_some() || variables.heightmap.is_some() {
+            continue;
+        }
+        if waiting[block.index] {
+            continue;
+        }
+
+        if simulation.stepping(block.index) {
             if block.life_time == variables.life_time {
-                commands
-                    .spawn(PbrBundle {
-                        mesh: meshes.add(Mesh::from(shape::Cube {
-                            size: rand::thread_rng().gen_range(0.07 * SCALE..0.6 * SCALE),
-                        })),
-                        material: materials.add(StandardMaterial {
-                            base_color: variables.base_color,
-                            ..default()
-                        }),
-                        transform: Transform {
-                            translation: get_random_direction(
-                                transform.translation,
-                                &variables.bounds,
-                                variables.scale,
-                            ),
-                            // rotation: random_rotation,
-                            ..default()
-                        },
-                        ..Default::default()
-                    })
-                    .insert(AutoCube {
-                        life_time: variables.life_time,
-                        index: block.index,
-                    });
+                let mask = variables.mask.as_ref().and_then(|mask| masks.get(mask));
+                let mut children = Vec::new();
+                for _ in 0..ATTEMPTS {
+                    children = variables.growth.rule().children(
+                        transform.translation,
+                        &variables,
+                        &clusters[block.index],
+                        rng,
+                    );
+                    // masked chunks only keep the children that landed inside
+                    let (Some(settings), Some(image)) = (&variables.mask, mask) else {
+                        break;
+                    };
+                    children.retain(|child| image.contains(&variables, settings, *child));
+                    if !children.is_empty() {
+                        break;
+                    }
+                }
+                children.truncate(budgets[block.index]);
+                budgets[block.index] -= children.len();
+                simulation.spawned(block.index, children.len());
+
+                for translation in children {
+                    let size = rng.gen_range(0.07 * SCALE..0.6 * SCALE);
+                    let color = cube_color(mask, &variables, translation);
+                    commands
+                        .spawn(PbrBundle {
+                            mesh: meshes.add(Mesh::from(shape::Cube { size })),
+                            material: materials.add(StandardMaterial {
+                                base_color: color.unwrap_or(variables.base_color),
+                                ..default()
+                            }),
+                            transform: Transform {
+                                translation,
+                                // rotation: random_rotation,
+                                ..default()
+                            },
+                            ..Default::default()
+                        })
+                        .insert(AutoCube {
+                            life_time: variables.life_time,
+                            index: block.index,
+                            size,
+                            color,
+                        });
+                }
             }
 
             block.life_time -= 1;
             if block.life_time == 0 {
                 commands.get_entity(entity).unwrap().despawn_recursive();
+                simulation.despawned(block.index);
             }
         }
-        let mut c = variables.base_color;
+        let mut c = block.color.unwrap_or(variables.base_color);
         let life_percent = block.life_time as f32 / variables.life_time as f32;
 
         // let _ = match variables.inter_color {
@@ -60,6 +134,12 @@ pub fn update_block(
         //     ColorChannels::A => c.set_a(life_percent),
         // };
 
+        let unchanged = materials
+            .get(material)
+            .is_some_and(|m| m.base_color == c && m.perceptual_roughness == 0.9);
+        if unchanged {
+            continue;
+        }
         let m = StandardMaterial {
             base_color: c,
             perceptual_roughness: 0.9,
@@ -68,16 +148,3 @@ pub fn update_block(
         let _ = materials.set(material, m);
     }
 }
-
-fn get_random_direction(cur: Vec3, bounds: &Bounds, scale: f32) -> Vec3 {
-    let x = get_random_f32(cur.x, bounds.min.x, bounds.max.x, scale);
-    let y = get_random_f32(cur.y, bounds.min.y, bounds.max.y, scale);
-    let z = get_random_f32(cur.z, bounds.min.z, bounds.max.z, scale);
-    Vec3::new(x, y, z)
-}
-
-fn get_random_f32(c: f32, bound_min: f32, bound_max: f32, scale: f32) -> f32 {
-    let range: f32 = rand::thread_rng().gen_range(-0.4 * scale..0.4 * scale);
-    let value = range + c;
-    value.clamp(bound_min * scale, bound_max * scale)
-}